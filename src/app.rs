@@ -12,14 +12,21 @@
 //! ```
 
 use crate::{
-    utils::{Prefix, Read},
-    Manifest, Post, Templates,
+    assets::{self, Assets},
+    compress,
+    engine::TemplateEngine,
+    minify::Minify,
+    og::{self, OG_DIR},
+    post::BUNDLE_INDEX,
+    scss,
+    utils::{self, Prefix, Read},
+    Manifest, Post, Theme, THEME_MANIFEST,
 };
 use anyhow::Result;
-use handlebars::Handlebars;
 use serde_json::{Map, Value};
 use std::{
-    fs::{self, File},
+    collections::BTreeMap,
+    fs,
     path::{Path, PathBuf},
 };
 
@@ -29,34 +36,51 @@ pub const LIVERELOAD_ENDPOINT: &str = "__livereload";
 /// The root of the site.
 #[derive(Clone, Debug)]
 pub struct App<'app> {
-    /// The handlebars instance.
-    pub handlebars: Handlebars<'app>,
+    /// The template engine.
+    pub engine: Box<dyn TemplateEngine + 'app>,
     /// The sonata.toml manifest.
     pub manifest: Manifest,
     /// Whether to enable livereload.
     pub livereload: bool,
     /// The posts.
     pub posts: Vec<Post>,
+    /// The fingerprinted assets.
+    pub assets: Assets,
+    /// The stylesheets of the page types, updated once per build.
+    pub styles: BTreeMap<String, PathBuf>,
 }
 
 impl TryFrom<Manifest> for App<'_> {
     type Error = anyhow::Error;
 
     fn try_from(manifest: Manifest) -> Result<Self> {
-        let mut handlebars = Handlebars::new();
-        handlebars.set_prevent_indent(true);
-        handlebars.set_strict_mode(true);
-        handlebars.register_embed_templates_with_extension::<Templates>(".hbs")?;
-
         Ok(Self {
-            handlebars,
+            engine: manifest.engine.load(&manifest)?,
             livereload: false,
             posts: manifest.posts()?,
+            // the hashed files of the previous builds are cleaned with it.
+            assets: if manifest.fingerprint {
+                assets::load(&manifest.out)
+            } else {
+                Assets::new()
+            },
+            styles: manifest.page_styles()?,
             manifest,
         })
     }
 }
 
+/// If the path is under any of the existing directories.
+fn under(dirs: impl IntoIterator<Item = PathBuf>, path: &Path) -> Result<bool> {
+    for dir in dirs {
+        if dir.exists() && dir.is_sub(path)? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
 impl App<'_> {
     /// Make initial data for templates
     pub fn data(&self, mut value: Value) -> Result<Value> {
@@ -65,7 +89,13 @@ impl App<'_> {
         map.insert("site".into(), self.manifest.site.clone().into());
         map.insert("title".into(), self.manifest.title.clone().into());
         map.insert("base".into(), self.manifest.base.clone().into());
-        map.insert("image".into(), self.manifest.image.clone().into());
+        let image = match self.manifest.image.as_str() {
+            image if !image.is_empty() && !self.manifest.url.is_empty() => {
+                self.manifest.absolute(image)
+            }
+            image => image.into(),
+        };
+        map.insert("image".into(), image.into());
         map.insert("twitter".into(), self.manifest.site.clone().into());
         map.insert(
             "favicon".into(),
@@ -80,6 +110,8 @@ impl App<'_> {
             map.insert("livereload".into(), LIVERELOAD_ENDPOINT.into());
         }
 
+        map.insert("assets".into(), serde_json::to_value(&self.assets)?);
+
         if let Some(data) = value.as_object_mut() {
             map.append(data);
         }
@@ -100,54 +132,138 @@ impl App<'_> {
 
     /// Conditional render the site
     pub fn crender(&mut self, paths: Vec<PathBuf>) -> Result<()> {
-        let mut templates_changed = false;
+        let (mut templates_changed, mut assets_changed) = (false, false);
         for path in paths {
             if self.manifest.posts.exists() && self.manifest.posts.is_sub(&path)? {
-                tracing::trace!("rendering post: {path:?} ...");
-                self.render_post(Post::load(&path)?)?;
-            } else if self.manifest.theme.exists() && self.manifest.theme.is_sub(&path)? {
+                // the changes in a page bundle render the whole bundle.
+                let path = self.post_root(&path)?;
+                if path.is_dir() && !path.join(BUNDLE_INDEX).exists() {
+                    continue;
+                } else if path.exists() {
+                    tracing::trace!("rendering post: {path:?} ...");
+                    self.render_post(self.manifest.post(&path)?)?;
+                } else {
+                    tracing::trace!("removing post: {path:?} ...");
+                    self.remove_post(&path)?;
+                }
+            } else if under(self.manifest.template_dirs(), &path)? {
+                tracing::info!("reloading templates ...");
+                templates_changed = true;
+                self.register_templates()?;
+            } else if under(self.manifest.themes.iter().map(Theme::assets), &path)? {
+                tracing::trace!("copying theme assets: {path:?} ...");
+                assets_changed = true;
+                self.manifest.copy_assets()?;
+            } else if under(self.manifest.theme_paths()?, &path)? {
+                if path.ends_with(THEME_MANIFEST) {
+                    tracing::warn!("{path:?} changed, restart to apply the theme defaults");
+                }
+
+                // pages link to the added or removed page stylesheets.
+                let output = self
+                    .manifest
+                    .out
+                    .join(path.with_extension("css").file_name()?);
+                if path
+                    .extension()
+                    .is_some_and(|ext| scss::EXTENSIONS.iter().any(|e| ext == *e))
+                    && !path.file_name()?.starts_with('_')
+                    && path.exists() != output.exists()
+                {
+                    templates_changed = true;
+                    for output in [output.clone(), output.with_extension("css.map")] {
+                        if output.exists() {
+                            fs::remove_file(output)?;
+                        }
+                    }
+                }
+
                 tracing::trace!("rendering theme: {path:?} ...");
+                assets_changed = true;
                 self.render_theme()?;
             } else if self.manifest.public.exists() && self.manifest.public.is_sub(&path)? {
-                tracing::trace!("copying public: {path:?} ...");
-                self.manifest.copy_public()?;
-            } else if self.manifest.templates.exists() && self.manifest.templates.is_sub(&path)? {
-                tracing::info!("reloading templates ...");
+                tracing::trace!("syncing public: {path:?} ...");
+                assets_changed = true;
+                self.manifest.sync_public(&path)?;
+
+                // posts picture the images of the public directory.
+                templates_changed |= self.manifest.images().is_some();
+            } else if self.manifest.helpers.exists() && self.manifest.helpers.is_sub(&path)? {
+                tracing::info!("reloading helpers ...");
                 templates_changed = true;
-                self.register_templates()?;
+                self.register_helpers()?;
             } else if self.manifest.favicon.exists() && self.manifest.favicon == path {
                 tracing::trace!("skipping {path:?} ...");
             }
         }
 
+        // pages link to the new names of the changed assets.
+        if assets_changed && self.fingerprint()? {
+            templates_changed = true;
+        }
+
+        self.styles = self.manifest.page_styles()?;
         let posts = self.manifest.posts()?;
         if templates_changed {
             self.render_posts(posts.clone())?;
+            self.render_404()?;
         }
-        self.render_index(posts)
+        self.render_index(posts)?;
+        self.precompress()
+    }
+
+    /// The post of the changed path, the file of the post or the
+    /// directory of the page bundle.
+    fn post_root(&self, path: &Path) -> Result<PathBuf> {
+        let posts = utils::canonicalize(&self.manifest.posts)?;
+        let path = utils::canonicalize(path)?;
+        Ok(match path.strip_prefix(&posts)?.components().next() {
+            Some(name) => posts.join(name),
+            None => path,
+        })
+    }
+
+    /// Register the builtin helpers and the script helpers, templates
+    /// are registered again since engines may drop them.
+    pub fn register_helpers(&mut self) -> Result<()> {
+        self.engine.register_helpers(&self.manifest.helpers)?;
+        self.register_templates()
     }
 
     /// Register templates if exist.
+    ///
+    /// Templates are re-registered from scratch so removed templates
+    /// fall back to the embedded defaults.
     pub fn register_templates(&mut self) -> Result<()> {
-        if self.manifest.templates.exists() {
-            self.handlebars
-                .register_templates_directory(&self.manifest.templates, Default::default())?;
-        }
-
-        Ok(())
+        self.engine
+            .register_templates(&self.manifest.template_dirs())
     }
 
     /// Render the site.
     pub fn render(&mut self) -> Result<()> {
         fs::create_dir_all(&self.manifest.out)?;
+        self.manifest.copy_assets()?;
         self.manifest.copy_public()?;
-        self.register_templates()?;
+        self.register_helpers()?;
         self.render_theme()?;
+        self.fingerprint()?;
+        self.styles = self.manifest.page_styles()?;
 
         let posts = self.manifest.posts()?;
         self.render_posts(posts.clone())?;
         self.render_index(posts)?;
-        self.render_favicon()
+        self.render_404()?;
+        self.render_favicon()?;
+        self.precompress()
+    }
+
+    /// Render the 404 page.
+    pub fn render_404(&self) -> Result<()> {
+        self.render_template(
+            "404.html",
+            "404",
+            serde_json::json!({ "tab": format!("404 · {}", self.manifest.title) }),
+        )
     }
 
     /// Render the favicon.
@@ -169,18 +285,98 @@ impl App<'_> {
         )
     }
 
-    /// Render post.
+    /// Render post, the assets of the page bundle are copied next to it.
     pub fn render_post(&self, post: Post) -> Result<()> {
-        self.render_template(
-            PathBuf::from(&post.index.link),
-            "post",
-            serde_json::json!({
-                "post": post,
-                "tab": post.meta.title,
-                "description": post.meta.description,
-                "twitter": post.meta.twitter,
-            }),
-        )
+        let output = post.output()?;
+        if post.bundle {
+            let dir = self.manifest.out.join(&output).with_file_name("");
+            if dir.exists() {
+                fs::remove_dir_all(&dir)?;
+            }
+
+            fs::create_dir_all(&dir)?;
+            let images = self.manifest.images();
+            for asset in post.assets()? {
+                let (source, target) = (post.path.join(&asset), dir.join(&asset));
+                if let Some(images) = &images {
+                    images.copy(&source, &target)?;
+                    continue;
+                }
+
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(source, target)?;
+            }
+        }
+
+        let image = self.og_image(&post)?;
+        let mut data = serde_json::json!({
+            "post": post,
+            "tab": post.meta.title,
+            "description": post.meta.description,
+            "twitter": post.meta.twitter,
+        });
+        if let (Some(image), Some(map)) = (image, data.as_object_mut()) {
+            map.insert("image".into(), image.into());
+        }
+
+        self.render_template(output, "post", data)
+    }
+
+    /// The Open Graph image of the post, the `image` of the post or the
+    /// rendered one if `og_image` is enabled, absolute with `url`.
+    pub fn og_image(&self, post: &Post) -> Result<Option<String>> {
+        let name = post.name()?;
+        let image = &post.meta.image;
+        if !image.is_empty() {
+            let image = if post.bundle && !image.starts_with('/') && !image.contains(':') {
+                format!("posts/{name}/{image}")
+            } else {
+                image.clone()
+            };
+            return Ok(Some(self.manifest.absolute(&image)));
+        }
+
+        if !self.manifest.og_image {
+            return Ok(None);
+        }
+
+        let image = format!("{OG_DIR}/{name}.png");
+        og::render(
+            &self.manifest.og_template()?,
+            post,
+            &self.manifest.title,
+            &self.manifest.out.join(&image),
+        )?;
+        Ok(Some(self.manifest.absolute(&image)))
+    }
+
+    /// Remove the rendered output of a deleted post or page bundle.
+    pub fn remove_post(&self, path: &Path) -> Result<()> {
+        let posts = self.manifest.out.join("posts");
+        let html = posts.join(path.with_extension("html").file_name()?);
+        if html.exists() {
+            tracing::info!("removing {html:?} ...");
+            fs::remove_file(html)?;
+        }
+
+        let bundle = posts.join(path.to_path_buf().file_name()?);
+        if bundle.is_dir() {
+            tracing::info!("removing {bundle:?} ...");
+            fs::remove_dir_all(bundle)?;
+        }
+
+        let image = self
+            .manifest
+            .out
+            .join(OG_DIR)
+            .join(path.with_extension("png").file_name()?);
+        if image.exists() {
+            tracing::info!("removing {image:?} ...");
+            fs::remove_file(image)?;
+        }
+        Ok(())
     }
 
     /// Render the posts.
@@ -194,7 +390,47 @@ impl App<'_> {
 
     /// Write theme to the output directory.
     pub fn render_theme(&self) -> Result<()> {
-        self.manifest.write_theme(&self.manifest.out)
+        self.manifest.write_theme(&self.manifest.out, self.minify())
+    }
+
+    /// Write the precompressed siblings of the output if enabled.
+    pub fn precompress(&self) -> Result<()> {
+        if !self.manifest.precompress {
+            return Ok(());
+        }
+
+        tracing::info!("precompressing {:?} ...", self.manifest.out);
+        compress::precompress(
+            &self.manifest.out,
+            self.manifest.compress_level,
+            self.manifest.compress_threshold,
+        )
+    }
+
+    /// Fingerprint the theme and public assets if enabled, returns if
+    /// the fingerprinted names changed.
+    pub fn fingerprint(&mut self) -> Result<bool> {
+        if !self.manifest.fingerprint {
+            return Ok(false);
+        }
+
+        let assets = assets::fingerprint(
+            &self.manifest.out,
+            self.manifest.asset_names()?,
+            &self.assets,
+        )?;
+        let changed = assets != self.assets;
+        self.assets = assets;
+        Ok(changed)
+    }
+
+    /// The output types to minify, nothing is minified with livereload.
+    pub fn minify(&self) -> Minify {
+        if self.livereload {
+            Minify::NONE
+        } else {
+            self.manifest.minify
+        }
     }
 
     /// Render a template.
@@ -206,8 +442,24 @@ impl App<'_> {
     ) -> Result<()> {
         let path = self.manifest.out.join(name);
         tracing::info!("rendering {path:?} ...");
-        self.handlebars
-            .render_to_write(template, &self.data(data)?, File::create(path)?)
-            .map_err(Into::into)
+
+        let mut data = self.data(data)?;
+        if let Some(map) = data.as_object_mut() {
+            map.insert("page".into(), template.into());
+            if self.styles.contains_key(template) {
+                map.insert("stylesheet".into(), format!("{template}.css").into());
+            }
+        }
+
+        let mut html = Vec::new();
+        self.engine.render(template, &data, &mut html)?;
+
+        let minify = self.minify();
+        if minify.html {
+            html = minify.html(&html);
+        }
+
+        fs::write(path, html)?;
+        Ok(())
     }
 }
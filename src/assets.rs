@@ -0,0 +1,99 @@
+//! Fingerprinted assets.
+//!
+//! With `fingerprint = true`, the theme files and the public files are
+//! copied to content-hash names next to their stable names, e.g.
+//! `theme.css` -> `theme.1f2e3d4c.css`, the map of them is written to
+//! `assets.json` and resolved by the `asset` helper of the templates.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The asset manifest in the output directory.
+pub const ASSET_MANIFEST: &str = "assets.json";
+
+/// The length of the content hash in the file names.
+const HASH_LEN: usize = 8;
+
+/// The map of the logical asset names to the fingerprinted names.
+pub type Assets = BTreeMap<String, String>;
+
+/// Load the assets of the previous build from the output directory.
+pub fn load(out: &Path) -> Assets {
+    fs::read_to_string(out.join(ASSET_MANIFEST))
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Fingerprint the files in the output directory, `names` are relative
+/// to it, hashed copies of the previous assets that are gone are removed.
+pub fn fingerprint(out: &Path, names: Vec<String>, previous: &Assets) -> Result<Assets> {
+    let mut assets = Assets::new();
+    for name in names {
+        let path = out.join(&name);
+        if !path.is_file() {
+            continue;
+        }
+
+        let hashed = hashed(&name, &fs::read(&path)?);
+        let target = out.join(&hashed);
+        if !target.exists() {
+            tracing::debug!("fingerprinting {name} -> {hashed}");
+            fs::copy(&path, target)?;
+        }
+
+        assets.insert(name, hashed);
+    }
+
+    for hashed in previous.values() {
+        if hashed.split(['/', '\\']).any(|s| s == "..") {
+            continue;
+        }
+
+        if !assets.values().any(|h| h == hashed) && out.join(hashed).exists() {
+            tracing::debug!("removing stale asset {hashed}");
+            fs::remove_file(out.join(hashed))?;
+        }
+    }
+
+    fs::write(
+        out.join(ASSET_MANIFEST),
+        serde_json::to_string_pretty(&assets)?,
+    )?;
+    Ok(assets)
+}
+
+/// The name with the content hash before the extension.
+fn hashed(name: &str, content: &[u8]) -> String {
+    let hash = Sha256::digest(content)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+
+    let path = PathBuf::from(name);
+    let hash = &hash[..HASH_LEN];
+    let file = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(ext)) => format!(
+            "{}.{hash}.{}",
+            stem.to_string_lossy(),
+            ext.to_string_lossy()
+        ),
+        _ => format!("{name}.{hash}"),
+    };
+
+    path.with_file_name(file)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+#[test]
+fn hash() {
+    assert_eq!(hashed("theme.css", b""), "theme.e3b0c442.css");
+    assert_eq!(hashed("public/a/b.png", b""), "public/a/b.e3b0c442.png");
+    assert_eq!(hashed("LICENSE", b""), "LICENSE.e3b0c442");
+}
@@ -21,8 +21,7 @@ pub struct Build {
 impl Build {
     /// Run the build command.
     pub fn run(&self) -> Result<()> {
-        let mut manifest = Manifest::load(&self.dir)?;
-        manifest.merge(self.manifest.clone());
+        let manifest = Manifest::load_with(&self.dir, self.manifest.clone())?;
 
         let output = manifest.out.clone();
         App::<'_>::try_from(manifest)?.render()?;
@@ -0,0 +1,161 @@
+//! Development dashboard of serve.
+
+use crate::{utils::Read, Manifest};
+use anyhow::Result;
+use chrono::Local;
+use handlebars::Handlebars;
+use serde::Serialize;
+use std::{fs, path::PathBuf, time::Instant};
+
+/// The endpoint of the dashboard.
+pub const DASHBOARD_ENDPOINT: &str = "__sonata";
+
+/// The template of the dashboard.
+const DASHBOARD: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>sonata · {{ manifest.title }}</title>
+  <style>
+    body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+    table { border-collapse: collapse; width: 100%; margin-bottom: 2rem; }
+    th, td { text-align: left; padding: .4rem .8rem; border-bottom: 1px solid #ddd; vertical-align: top; }
+    pre { background: #f5f5f5; padding: 1rem; overflow: auto; }
+    .rendered { color: #15803d; } .missing { color: #b45309; } .error { color: #b91c1c; }
+  </style>
+</head>
+<body>
+  <h1>{{ manifest.title }}</h1>
+
+  <h2>Last build</h2>
+  {{#if report.time}}
+  <p>{{ report.time }} · {{ report.duration }}ms</p>
+  <ul>
+  {{#each report.paths}}
+    <li>{{ this }}</li>
+  {{/each}}
+  </ul>
+  {{else}}
+  <p>No builds yet.</p>
+  {{/if}}
+  {{#each report.errors}}
+  <pre class="error">{{ this }}</pre>
+  {{/each}}
+
+  <h2>Posts</h2>
+  <table>
+    <thead><tr><th>File</th><th>Status</th><th>Title</th><th>Labels</th><th>Output</th></tr></thead>
+    <tbody>
+    {{#each posts}}
+      <tr>
+        <td>{{ file }}</td>
+        <td class="{{ status }}">{{ status }}{{#if error}}<pre>{{ error }}</pre>{{/if}}</td>
+        <td>{{ title }}</td>
+        <td>{{#each labels}}{{ this }} {{/each}}</td>
+        <td>{{#if link}}<a href="{{ link }}">{{ link }}</a>{{/if}}</td>
+      </tr>
+    {{/each}}
+    </tbody>
+  </table>
+
+  <h2>Manifest</h2>
+  <pre>{{ toml }}</pre>
+</body>
+</html>
+"#;
+
+/// The report of the last build.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Report {
+    /// When the build finished.
+    pub time: String,
+    /// The duration of the build in milliseconds.
+    pub duration: u128,
+    /// The changed paths triggered the build.
+    pub paths: Vec<PathBuf>,
+    /// The errors of the build.
+    pub errors: Vec<String>,
+}
+
+impl Report {
+    /// Record the result of a build.
+    pub fn record(&mut self, paths: Vec<PathBuf>, started: Instant, result: &Result<()>) {
+        self.time = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        self.duration = started.elapsed().as_millis();
+        self.paths = paths;
+        self.errors = match result {
+            Ok(_) => Vec::new(),
+            Err(e) => vec![format!("{e:#}")],
+        };
+    }
+}
+
+/// The status of a post in the posts directory.
+#[derive(Debug, Default, Serialize)]
+struct Status {
+    /// The file name of the post.
+    file: String,
+    /// One of `rendered`, `missing` or `error`.
+    status: &'static str,
+    /// The error of loading the post.
+    error: Option<String>,
+    /// The title of the post.
+    title: String,
+    /// The labels of the post.
+    labels: Vec<String>,
+    /// The link to the output of the post.
+    link: Option<String>,
+}
+
+/// Scan the posts directory for the status of the posts.
+fn posts(manifest: &Manifest) -> Result<Vec<Status>> {
+    let mut posts = Vec::new();
+    if !manifest.posts.exists() {
+        return Ok(posts);
+    }
+
+    for entry in fs::read_dir(&manifest.posts)? {
+        let path = entry?.path();
+        let mut status = Status {
+            file: path.file_name()?,
+            ..Default::default()
+        };
+
+        match manifest.post(&path) {
+            Ok(post) => {
+                status.status = if manifest.out.join(post.output()?).exists() {
+                    "rendered"
+                } else {
+                    "missing"
+                };
+                status.title = post.meta.title;
+                status.labels = post.meta.labels;
+                status.link = Some(post.index.link);
+            }
+            Err(e) => {
+                status.status = "error";
+                status.error = Some(format!("{e:#}"));
+            }
+        }
+
+        posts.push(status);
+    }
+
+    posts.sort_by(|a, b| b.file.cmp(&a.file));
+    Ok(posts)
+}
+
+/// Render the dashboard page.
+pub fn render(manifest: &Manifest, report: &Report) -> Result<String> {
+    Handlebars::new()
+        .render_template(
+            DASHBOARD,
+            &serde_json::json!({
+                "manifest": manifest,
+                "posts": posts(manifest)?,
+                "report": report,
+                "toml": toml::to_string_pretty(manifest)?,
+            }),
+        )
+        .map_err(Into::into)
+}
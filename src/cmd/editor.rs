@@ -0,0 +1,360 @@
+//! Local in-browser post editor of serve.
+
+use crate::{cmd::DASHBOARD_ENDPOINT, Meta, Post};
+use serde::Serialize;
+use std::{fs, path::PathBuf, sync::Arc};
+use warp::{
+    filters::BoxedFilter,
+    http::{Method, StatusCode},
+    hyper::body::Bytes,
+    reply::{Reply, Response},
+    Filter,
+};
+
+/// The endpoint of the editor, under the dashboard.
+pub const EDITOR_ENDPOINT: &str = "editor";
+
+/// The header required by the write requests of the editor API.
+///
+/// Browsers send a preflight request for custom headers, which keeps
+/// other sites from writing posts through the local server.
+const EDITOR_HEADER: &str = "x-sonata-editor";
+
+/// The hosts of the editor on the local machine.
+///
+/// The `Host` of the requests is checked against them, since the pages
+/// of other sites are same-origin after DNS rebinding and could set
+/// [`EDITOR_HEADER`].
+pub const LOCAL_HOSTS: [&str; 3] = ["localhost", "127.0.0.1", "[::1]"];
+
+/// The max size of a post in bytes.
+const MAX_POST_SIZE: u64 = 1024 * 1024;
+
+/// The page of the editor.
+const EDITOR: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>sonata · editor</title>
+  <style>
+    body { font-family: system-ui, sans-serif; margin: 0; display: flex; height: 100vh; }
+    aside { width: 18rem; border-right: 1px solid #ddd; padding: 1rem; overflow: auto; }
+    aside a { display: block; padding: .2rem 0; cursor: pointer; color: #1d4ed8; }
+    main { flex: 1; display: flex; flex-direction: column; padding: 1rem; }
+    textarea { flex: 1; font-family: ui-monospace, monospace; font-size: 14px; padding: .5rem; }
+    #status { margin: .5rem 0; min-height: 1.2rem; white-space: pre-wrap; }
+    .error { color: #b91c1c; }
+  </style>
+</head>
+<body>
+  <aside>
+    <button id="new">New post</button>
+    <nav id="posts"></nav>
+  </aside>
+  <main>
+    <div><strong id="name">Select a post</strong> <button id="save" disabled>Save</button></div>
+    <div id="status"></div>
+    <textarea id="content" disabled></textarea>
+  </main>
+  <script>
+    const api = "/__sonata/editor/api/posts";
+    const headers = { "x-sonata-editor": "1", "content-type": "text/markdown" };
+    let current = null, created = false;
+
+    function status(text, error) {
+      const el = document.getElementById("status");
+      el.textContent = text;
+      el.className = error ? "error" : "";
+    }
+
+    async function list() {
+      const nav = document.getElementById("posts");
+      nav.innerHTML = "";
+      for (const name of await (await fetch(api)).json()) {
+        const a = document.createElement("a");
+        a.textContent = name;
+        a.onclick = () => open(name);
+        nav.appendChild(a);
+      }
+    }
+
+    async function open(name) {
+      const res = await fetch(`${api}/${name}`);
+      if (!res.ok) return status(await res.text(), true);
+      load(name, await res.text(), false);
+    }
+
+    function load(name, content, isNew) {
+      current = name;
+      created = isNew;
+      document.getElementById("name").textContent = name;
+      document.getElementById("content").value = content;
+      document.getElementById("content").disabled = false;
+      document.getElementById("save").disabled = false;
+      status("");
+    }
+
+    document.getElementById("new").onclick = () => {
+      const today = new Date().toISOString().slice(0, 10);
+      const name = prompt("File name of the post", `${today}-untitled.md`);
+      if (!name) return;
+      load(name, "---\nauthor: \"\"\ndescription: \"\"\nlabels: []\ntitle: \"\"\n---\n", true);
+    };
+
+    document.getElementById("save").onclick = async () => {
+      const res = await fetch(`${api}/${current}`, {
+        method: created ? "POST" : "PUT",
+        headers,
+        body: document.getElementById("content").value,
+      });
+      if (!res.ok) return status(await res.text(), true);
+      created = false;
+      status(`saved ${current}`);
+      list();
+    };
+
+    list();
+  </script>
+</body>
+</html>
+"#;
+
+/// Error of the editor API.
+struct Error(StatusCode, String);
+
+impl<E: Into<anyhow::Error>> From<E> for Error {
+    fn from(e: E) -> Self {
+        Self(StatusCode::INTERNAL_SERVER_ERROR, format!("{:#}", e.into()))
+    }
+}
+
+impl Reply for Error {
+    fn into_response(self) -> Response {
+        warp::reply::with_status(self.1, self.0).into_response()
+    }
+}
+
+/// The editor API over the posts directory.
+struct Editor {
+    /// The path of the posts.
+    posts: PathBuf,
+}
+
+impl Editor {
+    /// Get the path of the post with validated file name.
+    fn path(&self, name: &str) -> Result<PathBuf, Error> {
+        if name.starts_with('.')
+            || !name.ends_with(".md")
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        {
+            return Err(Error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid file name {name}, should be yyyy-mm-dd-title.md"),
+            ));
+        }
+
+        Ok(self.posts.join(name))
+    }
+
+    /// List the markdown files of the posts.
+    fn list(&self) -> Result<Response, Error> {
+        let mut names = Vec::new();
+        if self.posts.exists() {
+            for entry in fs::read_dir(&self.posts)? {
+                let name = entry?.file_name().to_string_lossy().to_string();
+                if self.path(&name).is_ok() {
+                    names.push(name);
+                }
+            }
+        }
+
+        names.sort_by(|a, b| b.cmp(a));
+        Ok(json(&names))
+    }
+
+    /// Read the markdown of a post.
+    fn read(&self, name: &str) -> Result<Response, Error> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(Error(StatusCode::NOT_FOUND, format!("{name} not found")));
+        }
+
+        Ok(fs::read_to_string(path)?.into_response())
+    }
+
+    /// Validate and write a post.
+    ///
+    /// Creates the post with `POST` and updates an existing post with
+    /// `PUT`, the watcher renders the post and reloads the pages.
+    fn write(&self, name: &str, method: Method, body: Bytes) -> Result<Response, Error> {
+        let path = self.path(name)?;
+        match (method, path.exists()) {
+            (Method::POST, true) => {
+                return Err(Error(
+                    StatusCode::CONFLICT,
+                    format!("{name} already exists"),
+                ))
+            }
+            (Method::PUT, false) => {
+                return Err(Error(StatusCode::NOT_FOUND, format!("{name} not found")))
+            }
+            (Method::POST | Method::PUT, _) => {}
+            (method, _) => {
+                return Err(Error(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    format!("method {method} is not allowed"),
+                ))
+            }
+        }
+
+        let content = String::from_utf8(body.to_vec())
+            .map_err(|_| Error(StatusCode::BAD_REQUEST, "post is not utf-8".into()))?;
+        validate(path.clone(), &content)
+            .map_err(|e| Error(StatusCode::UNPROCESSABLE_ENTITY, format!("{e:#}")))?;
+
+        fs::create_dir_all(&self.posts)?;
+        fs::write(&path, content)?;
+        tracing::info!("saved {path:?} from the editor");
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+}
+
+/// Validate the frontmatter and the file name of the post.
+fn validate(path: PathBuf, content: &str) -> anyhow::Result<()> {
+    let markdown = content.splitn(3, "---").collect::<Vec<_>>();
+    if markdown.len() != 3 || !markdown[0].trim().is_empty() {
+        return Err(anyhow::anyhow!("missing the yaml frontmatter of the post"));
+    }
+
+    markdown[1]
+        .parse::<Meta>()
+        .map_err(|e| anyhow::anyhow!("invalid frontmatter: {e}"))?;
+
+    let mut post: Post = content.parse()?;
+    post.path = path;
+    post.merge_meta().map(drop)
+}
+
+/// Serialize the value to a json response.
+fn json(value: &impl Serialize) -> Response {
+    warp::reply::json(value).into_response()
+}
+
+/// If the `Host` of the request is one of the hosts, the port is ignored.
+fn allowed(host: Option<&str>, hosts: &[String]) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+
+    let name = match host.find(']') {
+        Some(end) if host.starts_with('[') => &host[..=end],
+        _ => host.split(':').next().unwrap_or_default(),
+    };
+    hosts.iter().any(|h| h.eq_ignore_ascii_case(name))
+}
+
+/// The routes of the editor, rejected if the editor is not enabled or
+/// the `Host` of the request is not one of the hosts.
+pub fn routes(posts: PathBuf, enabled: bool, hosts: Vec<String>) -> BoxedFilter<(Response,)> {
+    let editor = Arc::new(Editor { posts });
+    let hosts = Arc::new(hosts);
+    let root = warp::any()
+        .and_then(move || async move {
+            if enabled {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one()
+        .and(warp::path(DASHBOARD_ENDPOINT))
+        .and(warp::path(EDITOR_ENDPOINT))
+        .and(warp::header::optional::<String>("host"))
+        .and_then(move |host: Option<String>| {
+            let hosts = hosts.clone();
+            async move {
+                if allowed(host.as_deref(), &hosts) {
+                    Ok(())
+                } else {
+                    tracing::warn!("rejected editor request with host {host:?}");
+                    Err(warp::reject::not_found())
+                }
+            }
+        })
+        .untuple_one();
+
+    let page = root
+        .clone()
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::html(EDITOR).into_response());
+
+    let api = root
+        .and(warp::path("api"))
+        .and(warp::path("posts"))
+        .and(warp::any().map(move || editor.clone()));
+
+    let list = api
+        .clone()
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|editor: Arc<Editor>| editor.list());
+
+    let read = api
+        .clone()
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|editor: Arc<Editor>, name: String| editor.read(&name));
+
+    let write = api
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::method())
+        .and(warp::header::optional::<String>(EDITOR_HEADER))
+        .and(warp::body::content_length_limit(MAX_POST_SIZE))
+        .and(warp::body::bytes())
+        .map(
+            |editor: Arc<Editor>, name: String, method, header: Option<String>, body| {
+                if header.is_none() {
+                    return Err(Error(
+                        StatusCode::FORBIDDEN,
+                        format!("missing header {EDITOR_HEADER}"),
+                    ));
+                }
+
+                editor.write(&name, method, body)
+            },
+        );
+
+    page.or(list
+        .or(read)
+        .unify()
+        .or(write)
+        .unify()
+        .map(|r: Result<Response, Error>| r.unwrap_or_else(Reply::into_response)))
+        .unify()
+        .boxed()
+}
+
+#[test]
+fn hosts() {
+    let hosts = LOCAL_HOSTS.map(String::from);
+    assert!(allowed(Some("localhost:3000"), &hosts));
+    assert!(allowed(Some("127.0.0.1"), &hosts));
+    assert!(allowed(Some("[::1]:3000"), &hosts));
+    assert!(!allowed(Some("attacker.example:3000"), &hosts));
+    assert!(!allowed(Some("localhost.attacker.example"), &hosts));
+    assert!(!allowed(None, &hosts));
+}
+
+#[test]
+fn validate_post() {
+    let path = PathBuf::from("posts/2024-01-01-hello-world.md");
+    assert!(validate(path.clone(), crate::TEMPLATE_POST.trim()).is_ok());
+    assert!(validate(path.clone(), "no frontmatter").is_err());
+    assert!(validate(path, "---\nauthor: [\n---\n").is_err());
+    assert!(validate("posts/hello.md".into(), crate::TEMPLATE_POST.trim()).is_err());
+}
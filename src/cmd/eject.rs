@@ -0,0 +1,119 @@
+//! Command eject
+
+use crate::{Manifest, Templates, OG_TEMPLATE};
+use anyhow::{anyhow, Result};
+use ccli::{clap, clap::Parser, clap::ValueEnum};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The files to eject.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Target {
+    /// The embedded templates of the template engine.
+    Templates,
+    /// The default theme and highlight files.
+    Theme,
+    /// Both the templates and the theme.
+    All,
+}
+
+/// Write the embedded templates and theme into the project.
+#[derive(Debug, Parser)]
+pub struct Eject {
+    /// The files to eject.
+    #[clap(value_enum, default_value = "all")]
+    pub target: Target,
+
+    /// The directory of the sonata project.
+    #[clap(short, long, default_value = ".")]
+    pub dir: PathBuf,
+
+    /// Overwrite the existing files.
+    #[clap(short, long)]
+    pub force: bool,
+}
+
+impl Eject {
+    /// Run the eject command.
+    pub fn run(&self) -> Result<()> {
+        let manifest = Manifest::load(&self.dir)?;
+        if self.target != Target::Theme {
+            self.templates(&manifest)?;
+        }
+
+        if self.target != Target::Templates {
+            for (_, path, default) in manifest.theme_files()? {
+                self.write(&path, default.as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Eject the embedded templates of the template engine.
+    fn templates(&self, manifest: &Manifest) -> Result<()> {
+        let extension = manifest.engine.extension();
+        for name in
+            Templates::iter().filter(|name| name.ends_with(extension) || name == OG_TEMPLATE)
+        {
+            let template = Templates::get(&name)
+                .ok_or_else(|| anyhow!("Could not find embedded template {name}"))?;
+            self.write(&manifest.templates.join(name.as_ref()), &template.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the file, existing files are skipped without `--force`.
+    fn write(&self, path: &Path, content: &[u8]) -> Result<()> {
+        if path.exists() && !self.force {
+            tracing::warn!("skipping {path:?}, already exists, use --force to overwrite");
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        tracing::info!("ejecting {path:?} ...");
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn eject() -> Result<()> {
+    let root = std::env::temp_dir().join("sonata-eject");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root)?;
+    fs::write(root.join("sonata.toml"), crate::MINIMAL_MANIFEST)?;
+
+    let mut eject = Eject {
+        target: Target::All,
+        dir: root.clone(),
+        force: false,
+    };
+    eject.run()?;
+
+    let (post, theme) = (
+        root.join("templates/post.hbs"),
+        root.join("theme/theme.css"),
+    );
+    assert!(post.exists() && theme.exists());
+    assert!(root.join("templates").join(OG_TEMPLATE).exists());
+    assert!(!root.join("templates/post.jinja").exists());
+
+    // the existing files are kept without `--force`.
+    fs::write(&post, "edited")?;
+    eject.run()?;
+    assert_eq!(fs::read_to_string(&post)?, "edited");
+
+    eject.force = true;
+    eject.run()?;
+    assert_ne!(fs::read_to_string(&post)?, "edited");
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
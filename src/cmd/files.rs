@@ -0,0 +1,269 @@
+//! Static files of serve with compression and caching headers.
+
+use crate::compress::{compressible, Encoding};
+use anyhow::Result;
+use percent_encoding::percent_decode_str;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use warp::{
+    filters::{path::Tail, BoxedFilter},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    hyper::Body,
+    reply::{Reply, Response},
+    Filter,
+};
+
+/// Files smaller than this size will not be compressed on the fly.
+const MIN_COMPRESS_SIZE: u64 = 1024;
+
+/// The compression level of the responses compressed on the fly.
+const COMPRESS_LEVEL: u32 = 5;
+
+/// Static files in the output directory.
+#[derive(Clone, Debug)]
+pub struct Files {
+    /// The output directory.
+    pub out: PathBuf,
+    /// Whether to compress the responses.
+    pub compress: bool,
+    /// The value of `Cache-Control`.
+    pub cache_control: String,
+}
+
+/// Resolve the request path to a file in the output directory, the
+/// path is percent-decoded and `..` segments are rejected.
+pub fn resolve(out: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let mut file = out.to_path_buf();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        if segment.starts_with("..")
+            || segment.contains('\\')
+            || (cfg!(windows) && segment.contains(':'))
+        {
+            return None;
+        }
+        file.push(segment);
+    }
+
+    if file.is_dir() {
+        file.push("index.html");
+    }
+
+    file.is_file().then_some(file)
+}
+
+impl Files {
+    /// Serve the file for the request.
+    fn serve(&self, file: &Path, headers: &HeaderMap) -> Result<Response> {
+        let mime = mime_guess::from_path(file).first_or_octet_stream();
+        let metadata = fs::metadata(file)?;
+        let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let version = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        let accept = headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        let encodings = if self.compress {
+            Encoding::negotiate(accept)
+        } else {
+            Vec::new()
+        };
+
+        // prefer the up-to-date precompressed siblings, then compress
+        // on the fly.
+        let mut encoding = None;
+        let mut path = file.to_path_buf();
+        for candidate in &encodings {
+            let sibling = candidate.sibling(file);
+            if fs::metadata(&sibling)
+                .and_then(|s| Ok(s.is_file() && s.modified()? >= modified))
+                .unwrap_or(false)
+            {
+                encoding = Some(*candidate);
+                path = sibling;
+                break;
+            }
+        }
+
+        if encoding.is_none()
+            && compressible(mime.essence_str())
+            && metadata.len() >= MIN_COMPRESS_SIZE
+        {
+            encoding = encodings.first().copied();
+        }
+
+        let etag = format!(
+            "\"{:x}-{version:x}{}\"",
+            metadata.len(),
+            encoding
+                .map(|e| format!("-{}", e.extension()))
+                .unwrap_or_default()
+        );
+
+        let mut builder = warp::http::Response::builder()
+            .header(header::ETAG, &etag)
+            .header(header::LAST_MODIFIED, httpdate::fmt_http_date(modified))
+            .header(header::CACHE_CONTROL, &self.cache_control);
+        if self.compress {
+            builder = builder.header(header::VARY, "accept-encoding");
+        }
+
+        if not_modified(headers, &etag, modified) {
+            return Ok(builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())?);
+        }
+
+        let mut body = fs::read(&path)?;
+        if let Some(encoding) = encoding {
+            if path == file {
+                body = encoding.compress(&body, COMPRESS_LEVEL)?;
+            }
+            builder = builder.header(header::CONTENT_ENCODING, encoding.name());
+        }
+
+        Ok(builder
+            .header(header::CONTENT_TYPE, mime.as_ref())
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))?)
+    }
+
+    /// The filter of the static files.
+    ///
+    /// Rejects range requests, which are handled by `warp::fs::dir`.
+    pub fn filter(&self) -> BoxedFilter<(Response,)> {
+        let files = self.clone();
+        warp::get()
+            .or(warp::head())
+            .unify()
+            .and(warp::path::tail())
+            .and(warp::header::headers_cloned())
+            .and_then(move |tail: Tail, headers: HeaderMap| {
+                let response = if headers.contains_key(header::RANGE) {
+                    None
+                } else {
+                    resolve(&files.out, tail.as_str()).map(|file| {
+                        files.serve(&file, &headers).unwrap_or_else(|e| {
+                            tracing::error!("failed to serve {file:?}: {e:#}");
+                            let mut response = Response::new(Body::empty());
+                            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                            response
+                        })
+                    })
+                };
+
+                async move { response.ok_or_else(warp::reject::not_found) }
+            })
+            .boxed()
+    }
+
+    /// The filter of the 404 page, the fallback of all routes.
+    pub fn not_found(&self) -> BoxedFilter<(Response,)> {
+        let page = self.out.join("404.html");
+        warp::any()
+            .map(move || {
+                let body = fs::read(&page).unwrap_or_else(|_| b"404 Not Found".to_vec());
+                let mut response = warp::reply::html(body).into_response();
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                response
+            })
+            .boxed()
+    }
+}
+
+/// Check the conditional headers of the request.
+fn not_modified(headers: &HeaderMap, etag: &str, modified: SystemTime) -> bool {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
+    };
+    if let Some(tags) = header(header::IF_NONE_MATCH) {
+        return tags
+            .split(',')
+            .map(|t| t.trim().trim_start_matches("W/"))
+            .any(|t| t == etag || t == "*");
+    }
+
+    header(header::IF_MODIFIED_SINCE)
+        .and_then(|since| httpdate::parse_http_date(since).ok())
+        .is_some_and(|since| {
+            let modified = modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let since = since
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            modified <= since
+        })
+}
+
+#[test]
+fn serve() -> Result<()> {
+    use std::time::Duration;
+
+    let out = std::env::temp_dir().join("sonata-files-serve");
+    let _ = fs::remove_dir_all(&out);
+    fs::create_dir_all(&out)?;
+    fs::write(out.join("index.html"), "<p>hello</p>".repeat(128))?;
+    fs::write(
+        Encoding::Gzip.sibling(&out.join("index.html")),
+        "precompressed",
+    )?;
+
+    let files = Files {
+        out: out.clone(),
+        compress: true,
+        cache_control: "no-cache".into(),
+    };
+    let filter = files.filter();
+    let request = |encoding: &str| {
+        warp::test::request()
+            .path("/")
+            .header(header::ACCEPT_ENCODING, encoding)
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        // the up-to-date sibling is served.
+        let response = request("gzip").reply(&filter).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.body().as_ref(), b"precompressed");
+
+        // the response is compressed on the fly without the sibling.
+        let response = request("br").reply(&filter).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+        assert!(response.body().len() < 128 * 12);
+
+        let etag = response.headers()[header::ETAG].clone();
+        let response = request("br")
+            .header(header::IF_NONE_MATCH, etag)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        // the stale sibling is ignored.
+        std::thread::sleep(Duration::from_millis(10));
+        fs::write(out.join("index.html"), "<p>edited</p>".repeat(128))?;
+        let response = request("gzip").reply(&filter).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(response.body().as_ref(), b"precompressed");
+
+        let response = request("identity").reply(&filter).await;
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        Ok::<_, anyhow::Error>(())
+    })?;
+
+    fs::remove_dir_all(&out)?;
+    Ok(())
+}
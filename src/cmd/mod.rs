@@ -1,12 +1,25 @@
 //! sonata cli
 #![cfg(feature = "cli")]
 
-pub use self::{build::Build, init::Init, serve::Serve, watch::Watch};
+pub use self::{
+    build::Build,
+    dashboard::{Report, DASHBOARD_ENDPOINT},
+    eject::Eject,
+    init::Init,
+    serve::{Address, Serve},
+    watch::Watch,
+};
 use ccli::{clap, clap::Parser, App};
 
 mod build;
+mod dashboard;
+mod editor;
+mod eject;
+mod files;
 mod init;
+mod rules;
 mod serve;
+mod tls;
 mod watch;
 
 /// sonata sub command.
@@ -14,6 +27,8 @@ mod watch;
 pub enum Command {
     /// Builds a sonata site from its markdown files
     Build(Box<Build>),
+    /// Writes the embedded templates and theme into a sonata site
+    Eject(Eject),
     /// Creates the boilerplate structure and files for a sonata site
     Init(Init),
     /// Serves a sonata site, and rebuilds it on changes
@@ -44,6 +59,7 @@ impl App for Sonata {
     fn run(&self) -> anyhow::Result<()> {
         match &self.command {
             Command::Build(build) => build.run(),
+            Command::Eject(eject) => eject.run(),
             Command::Init(init) => init.run(),
             Command::Serve(serve) => serve.run(),
             Command::Watch(watch) => watch.run(),
@@ -0,0 +1,421 @@
+//! Netlify-style `_redirects` and `_headers` rules of serve.
+//!
+//! The rules are copied from the public directory to the root of the
+//! output directory like the static hosts expect, and reloaded from
+//! there after every build.
+//!
+//! ```text
+//! # _redirects
+//! /news/:year/:month  /blog/:year-:month  301
+//! /docs/*             /guide/:splat       302
+//! /app/*              /index.html         200
+//! /old                /new                301!
+//!
+//! # _headers
+//! /*
+//!   X-Frame-Options: DENY
+//! /posts/*
+//!   Cache-Control: public, max-age=3600
+//! ```
+
+use crate::{cmd::files, utils::Read};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use warp::{
+    filters::{path::FullPath, BoxedFilter},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    hyper::Body,
+    reply::Response,
+    Filter,
+};
+
+/// The file of the redirect rules.
+pub const REDIRECTS: &str = "_redirects";
+
+/// The file of the header rules.
+pub const HEADERS: &str = "_headers";
+
+/// A path pattern with placeholders and a trailing splat.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern(Vec<String>);
+
+impl Pattern {
+    /// Parse pattern from string.
+    pub fn new(pattern: &str) -> Self {
+        Self(segments(pattern).map(Into::into).collect())
+    }
+
+    /// Match the path, returns the captured placeholders.
+    pub fn captures(&self, path: &str) -> Option<BTreeMap<String, String>> {
+        let mut captures = BTreeMap::new();
+        let mut parts = segments(path);
+        for (index, segment) in self.0.iter().enumerate() {
+            if segment == "*" && index == self.0.len() - 1 {
+                captures.insert("splat".into(), parts.collect::<Vec<_>>().join("/"));
+                return Some(captures);
+            }
+
+            let part = parts.next()?;
+            if let Some(name) = segment.strip_prefix(':') {
+                captures.insert(name.into(), part.into());
+            } else if segment != part {
+                return None;
+            }
+        }
+
+        parts.next().is_none().then_some(captures)
+    }
+}
+
+/// Split the path into non-empty segments.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// Replace the placeholders in the target with the captures.
+fn interpolate(target: &str, captures: &BTreeMap<String, String>) -> String {
+    let mut target = target.to_string();
+    let mut names = captures.keys().collect::<Vec<_>>();
+    names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+    for name in names {
+        target = target.replace(&format!(":{name}"), &captures[name]);
+    }
+    target
+}
+
+/// A rule of `_redirects`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    /// The pattern of the source path.
+    pub from: Pattern,
+    /// The target path or URL.
+    pub to: String,
+    /// The status code, `200` rewrites and `404` serves a custom page.
+    pub status: u16,
+    /// Apply the rule even if a file exists at the source path.
+    pub force: bool,
+}
+
+impl Redirect {
+    /// Parse the rules of `_redirects`.
+    pub fn parse(s: &str) -> Result<Vec<Self>> {
+        let mut rules = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            // `#` starts a comment at the start of the line or after
+            // whitespace, targets could include fragments.
+            let fields = line
+                .split_whitespace()
+                .take_while(|field| !field.starts_with('#'))
+                .collect::<Vec<_>>();
+            if fields.is_empty() {
+                continue;
+            }
+
+            if fields.len() < 2 {
+                return Err(anyhow!("{REDIRECTS}:{}: missing target", index + 1));
+            }
+
+            let (status, force) = match fields.get(2) {
+                Some(status) => {
+                    let force = status.ends_with('!');
+                    let status = status.trim_end_matches('!').parse().map_err(|_| {
+                        anyhow!("{REDIRECTS}:{}: invalid status {status}", index + 1)
+                    })?;
+                    (status, force)
+                }
+                None => (301, false),
+            };
+
+            rules.push(Self {
+                from: Pattern::new(fields[0]),
+                to: fields[1].into(),
+                status,
+                force,
+            });
+        }
+
+        Ok(rules)
+    }
+}
+
+/// A rule of `_headers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Headers {
+    /// The pattern of the path.
+    pub path: Pattern,
+    /// The headers to set.
+    pub headers: Vec<(String, String)>,
+}
+
+impl Headers {
+    /// Parse the rules of `_headers`.
+    pub fn parse(s: &str) -> Result<Vec<Self>> {
+        let mut rules: Vec<Self> = Vec::new();
+        for (index, line) in s.lines().enumerate() {
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with(char::is_whitespace) {
+                rules.push(Self {
+                    path: Pattern::new(line.trim()),
+                    headers: Vec::new(),
+                });
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("{HEADERS}:{}: invalid header {line:?}", index + 1))?;
+            rules
+                .last_mut()
+                .ok_or_else(|| anyhow!("{HEADERS}:{}: header without path", index + 1))?
+                .headers
+                .push((name.trim().into(), value.trim().into()));
+        }
+
+        Ok(rules)
+    }
+
+    /// Apply the matched header rules to the response.
+    pub fn apply(rules: &[Self], path: &str, headers: &mut HeaderMap) {
+        for rule in rules.iter().filter(|r| r.path.captures(path).is_some()) {
+            for (name, value) in &rule.headers {
+                match (
+                    HeaderName::try_from(name.as_str()),
+                    HeaderValue::try_from(value.as_str()),
+                ) {
+                    (Ok(name), Ok(value)) => {
+                        headers.insert(name, value);
+                    }
+                    _ => tracing::warn!("invalid header in {HEADERS}: {name}: {value}"),
+                }
+            }
+        }
+    }
+}
+
+/// The parsed rules of a build.
+#[derive(Clone, Debug, Default)]
+pub struct RuleSet {
+    /// The rules of `_redirects`.
+    pub redirects: Vec<Redirect>,
+    /// The rules of `_headers`.
+    pub headers: Vec<Headers>,
+}
+
+impl RuleSet {
+    /// Load the rules from the output directory.
+    pub fn load(out: &Path) -> Self {
+        Self {
+            redirects: load(out, REDIRECTS, Redirect::parse),
+            headers: load(out, HEADERS, Headers::parse),
+        }
+    }
+}
+
+/// Load and parse a rule file, returns nothing on errors.
+fn load<T>(out: &Path, name: &str, parse: fn(&str) -> Result<Vec<T>>) -> Vec<T> {
+    let path = out.join(name);
+    if !path.exists() {
+        return Vec::new();
+    }
+
+    path.read().and_then(|s| parse(&s)).unwrap_or_else(|e| {
+        tracing::error!("failed to load {path:?}: {e:#}");
+        Vec::new()
+    })
+}
+
+/// The rules of the site.
+#[derive(Clone, Debug)]
+pub struct Rules {
+    /// The output directory.
+    pub out: PathBuf,
+    /// The base URL of the site.
+    pub base: String,
+    /// The rules of the last build, reloaded by the watcher.
+    pub set: Arc<RwLock<RuleSet>>,
+}
+
+impl Rules {
+    /// Get the file of the path in the output directory, paths outside
+    /// the base or escaping the output directory are rejected like the
+    /// static files.
+    fn file(&self, path: &str) -> Option<PathBuf> {
+        let base = self.base.trim_end_matches('/');
+        let path = path
+            .strip_prefix(base)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))?;
+        files::resolve(&self.out, path)
+    }
+
+    /// Resolve the request with the redirect rules.
+    fn redirect(&self, path: &str, query: Option<String>) -> Option<Response> {
+        let set = self.set.read().ok()?;
+        for rule in &set.redirects {
+            let Some(captures) = rule.from.captures(path) else {
+                continue;
+            };
+
+            if !rule.force && self.file(path).is_some() {
+                continue;
+            }
+
+            let mut to = interpolate(&rule.to, &captures);
+            if let Some(query) = query.as_ref().filter(|_| !to.contains('?')) {
+                to = format!("{to}?{query}");
+            }
+
+            let Ok(status) = StatusCode::from_u16(rule.status) else {
+                tracing::warn!("invalid status {} in {REDIRECTS}", rule.status);
+                continue;
+            };
+
+            if status.is_redirection() {
+                tracing::debug!("redirecting {path} -> {to} ({status})");
+                return warp::http::Response::builder()
+                    .status(status)
+                    .header(header::LOCATION, to)
+                    .body(Body::empty())
+                    .ok();
+            }
+
+            let target = to.split('?').next().unwrap_or_default();
+            match self.file(target).map(|file| respond(&file, status)) {
+                Some(Ok(response)) => {
+                    tracing::debug!("rewriting {path} -> {target} ({status})");
+                    return Some(response);
+                }
+                Some(Err(e)) => tracing::error!("failed to serve {target}: {e:#}"),
+                None => tracing::debug!("skipping rule {path} -> {target}, not found"),
+            }
+        }
+
+        None
+    }
+
+    /// The filter of the redirect rules, rejects if no rule matches.
+    pub fn redirects(&self) -> BoxedFilter<(Response,)> {
+        let rules = self.clone();
+        warp::path::full()
+            .and(
+                warp::query::raw()
+                    .map(Some)
+                    .or(warp::any().map(|| None))
+                    .unify(),
+            )
+            .and_then(move |path: FullPath, query: Option<String>| {
+                let response = rules.redirect(path.as_str(), query);
+                async move { response.ok_or_else(warp::reject::not_found) }
+            })
+            .boxed()
+    }
+
+    /// Apply the header rules to the response.
+    pub fn headers(&self, path: &str, mut response: Response) -> Response {
+        if let Ok(set) = self.set.read() {
+            Headers::apply(&set.headers, path, response.headers_mut());
+        }
+        response
+    }
+}
+
+/// Respond with the file and the status.
+fn respond(file: &Path, status: StatusCode) -> Result<Response> {
+    let mime = mime_guess::from_path(file).first_or_octet_stream();
+    Ok(warp::http::Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, mime.as_ref())
+        .body(Body::from(fs::read(file)?))?)
+}
+
+#[test]
+fn redirects() -> Result<()> {
+    let rules = Redirect::parse(
+        r#"
+# comment
+/news/:year/:month  /blog/:year-:month
+/docs/*             /guide/:splat  302
+/old                /new           200!
+/faq                /docs#faq      302 # the fragment is kept
+"#,
+    )?;
+
+    assert_eq!(rules.len(), 4);
+    assert_eq!((rules[0].status, rules[0].force), (301, false));
+    assert_eq!((rules[2].status, rules[2].force), (200, true));
+    assert_eq!((rules[3].to.as_str(), rules[3].status), ("/docs#faq", 302));
+
+    let captures = rules[0].from.captures("/news/2024/01").unwrap();
+    assert_eq!(interpolate(&rules[0].to, &captures), "/blog/2024-01");
+    assert!(rules[0].from.captures("/news/2024").is_none());
+
+    let captures = rules[1].from.captures("/docs/a/b.html").unwrap();
+    assert_eq!(interpolate(&rules[1].to, &captures), "/guide/a/b.html");
+    Ok(())
+}
+
+#[test]
+fn headers() -> Result<()> {
+    let rules = Headers::parse(
+        r#"
+/*
+  X-Frame-Options: DENY
+/posts/*
+  Cache-Control: public, max-age=3600
+"#,
+    )?;
+
+    let mut headers = HeaderMap::new();
+    Headers::apply(&rules, "/posts/foo.html", &mut headers);
+    assert_eq!(headers["x-frame-options"], "DENY");
+    assert_eq!(headers["cache-control"], "public, max-age=3600");
+
+    let mut headers = HeaderMap::new();
+    Headers::apply(&rules, "/index.html", &mut headers);
+    assert!(headers.get("cache-control").is_none());
+    Ok(())
+}
+
+#[test]
+fn traversal() -> Result<()> {
+    let root = std::env::temp_dir().join("sonata-rules-traversal");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("out"))?;
+    fs::write(root.join("out").join(REDIRECTS), "/*  /:splat  200!")?;
+    fs::write(root.join("out/index.html"), "index")?;
+    fs::write(root.join("secret.txt"), "secret")?;
+
+    let rules = Rules {
+        out: root.join("out"),
+        base: "/".into(),
+        set: Arc::new(RwLock::new(RuleSet::load(&root.join("out")))),
+    };
+    assert!(rules.redirect("/index.html", None).is_some());
+    for path in [
+        "/../secret.txt",
+        "/..%2fsecret.txt",
+        "/a/%2e%2e/%2e%2e/secret.txt",
+    ] {
+        assert!(rules.redirect(path, None).is_none(), "{path}");
+    }
+
+    // the base is only stripped at a segment boundary.
+    fs::write(root.join("out/way"), "way")?;
+    let rules = Rules {
+        base: "/sub/".into(),
+        ..rules
+    };
+    assert_eq!(rules.file("/sub/way"), Some(root.join("out/way")));
+    assert_eq!(rules.file("/sub"), Some(root.join("out/index.html")));
+    assert!(rules.file("/subway").is_none());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
@@ -1,13 +1,20 @@
 //! Command serve
 
-use crate::{cmd::Watch, LIVERELOAD_ENDPOINT};
-use anyhow::Result;
+use crate::{
+    cmd::{
+        dashboard, editor, files::Files, rules::Rules, tls::Identity, Watch, DASHBOARD_ENDPOINT,
+    },
+    utils, LIVERELOAD_ENDPOINT,
+};
+use anyhow::{anyhow, Result};
 use async_lock::Mutex;
 use ccli::{clap, clap::Parser};
-use futures::{sink::SinkExt, FutureExt, StreamExt};
-use notify::Event;
+use futures::{future::BoxFuture, sink::SinkExt, FutureExt, StreamExt};
 use std::{
-    net::{Ipv4Addr, TcpListener},
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr,
     sync::{
         mpsc::{self, Receiver},
         Arc,
@@ -15,6 +22,8 @@ use std::{
 };
 use tokio::runtime::Runtime;
 use warp::{
+    filters::path::FullPath,
+    reply::Reply,
     ws::{Message, WebSocket, Ws},
     Filter,
 };
@@ -26,9 +35,39 @@ pub struct Serve {
     #[clap(short, long, default_value = "3000")]
     pub port: u16,
 
-    /// Address to listen on
+    /// Address to listen on, an IP address, a hostname or `unix:/path/to/socket`
     #[clap(short, long, default_value = "0.0.0.0")]
-    pub address: Ipv4Addr,
+    pub address: Address,
+
+    /// Serve over HTTPS, uses a self-signed certificate cached in
+    /// `.sonata/tls` if `--cert` and `--key` are not provided
+    #[clap(long)]
+    pub https: bool,
+
+    /// The TLS certificate in PEM format
+    #[clap(long, requires_all = ["https", "key"])]
+    pub cert: Option<PathBuf>,
+
+    /// The TLS private key in PEM format
+    #[clap(long, requires_all = ["https", "cert"])]
+    pub key: Option<PathBuf>,
+
+    /// Disable gzip and brotli compression of the responses
+    #[clap(long)]
+    pub no_compress: bool,
+
+    /// The `Cache-Control` header of the static files
+    #[clap(long, default_value = "no-cache")]
+    pub cache_control: String,
+
+    /// Serve the in-browser post editor at `/__sonata/editor`, binds to
+    /// localhost only unless `--editor-public` is given
+    #[clap(long)]
+    pub editor: bool,
+
+    /// Allow serving the post editor on non-local addresses
+    #[clap(long, requires = "editor")]
+    pub editor_public: bool,
 
     /// Watch configuration
     #[clap(flatten)]
@@ -36,28 +75,93 @@ pub struct Serve {
 }
 
 impl Serve {
-    /// Pick a port for the livereload server
-    fn pick(&self) -> u16 {
-        let mut base = self.port;
+    /// Get the address to listen on
+    ///
+    /// The post editor is bound to localhost unless `--editor-public`
+    /// is given.
+    fn address(&self) -> Result<Address> {
+        if !self.editor || self.editor_public || self.address.is_local() {
+            return Ok(self.address.clone());
+        }
+
+        match self.address {
+            Address::Ip(IpAddr::V4(ip)) if ip.is_unspecified() => {
+                tracing::info!("binding to localhost for the post editor ...");
+                Ok(Address::Ip(Ipv4Addr::LOCALHOST.into()))
+            }
+            Address::Ip(IpAddr::V6(ip)) if ip.is_unspecified() => {
+                tracing::info!("binding to localhost for the post editor ...");
+                Ok(Address::Ip(Ipv6Addr::LOCALHOST.into()))
+            }
+            _ => Err(anyhow!(
+                "the post editor only binds to localhost, pass `--editor-public` to serve it on {}",
+                self.address
+            )),
+        }
+    }
+
+    /// The hosts allowed in the requests of the post editor, the local
+    /// hosts and the address with `--editor-public`.
+    fn editor_hosts(&self) -> Vec<String> {
+        let mut hosts = editor::LOCAL_HOSTS.map(String::from).to_vec();
+        if self.editor_public {
+            hosts.push(match &self.address {
+                Address::Ip(IpAddr::V6(ip)) => format!("[{ip}]"),
+                address => address.to_string(),
+            });
+        }
+
+        hosts
+    }
+
+    /// Pick a socket address for the server
+    fn pick(&self, address: &Address) -> Result<SocketAddr> {
+        let mut port = self.port;
         loop {
-            if TcpListener::bind((self.address, base)).is_ok() {
-                return base;
+            let addrs = match address {
+                Address::Ip(ip) => vec![SocketAddr::new(*ip, port)],
+                Address::Host(host) => (host.as_str(), port).to_socket_addrs()?.collect(),
+                Address::Unix(path) => {
+                    return Err(anyhow!("unix socket {path:?} has no socket address"))
+                }
+            };
+
+            if addrs.is_empty() {
+                return Err(anyhow!("could not resolve address {address}"));
+            }
+
+            if let Some(addr) = addrs
+                .into_iter()
+                .find(|addr| TcpListener::bind(addr).is_ok())
+            {
+                return Ok(addr);
             }
 
-            base += 1;
+            port = port
+                .checked_add(1)
+                .ok_or_else(|| anyhow!("no available port on {address}"))?;
         }
     }
 
+    /// Get the TLS identity of the server
+    fn identity(&self, address: &Address) -> Result<Identity> {
+        if let (Some(cert), Some(key)) = (&self.cert, &self.key) {
+            return Identity::load(cert, key);
+        }
+
+        let root = utils::find_proj(&self.watch.dir)?;
+        Identity::self_signed(&root.join(".sonata").join("tls"), address)
+    }
+
     /// Run the serve command
     pub fn run(&self) -> Result<()> {
-        let port = self.pick();
-
-        let (tx, rx) = mpsc::channel::<Event>();
+        let address = self.address()?;
+        let (tx, rx) = mpsc::channel::<Vec<PathBuf>>();
         let rx = Arc::new(Mutex::new(rx));
         let livereload = warp::path(LIVERELOAD_ENDPOINT)
             .and(warp::ws())
             .and(warp::any().map(move || rx.clone()))
-            .map(|ws: Ws, rx: Arc<Mutex<Receiver<Event>>>| {
+            .map(|ws: Ws, rx: Arc<Mutex<Receiver<Vec<PathBuf>>>>| {
                 ws.on_upgrade(move |socket: WebSocket| async move {
                     let (mut tx, _) = socket.split();
                     let rx = rx.lock().await;
@@ -67,36 +171,232 @@ impl Serve {
                         }
                     }
                 })
+                .into_response()
             });
 
         let manifest = self.watch.manifest()?;
         let watcher = self.watch.clone();
-        let sonata = if manifest.base.is_empty() {
-            warp::fs::dir(manifest.out.clone()).boxed()
-        } else {
-            let mut base = warp::any().boxed();
-            for part in manifest.base.split('/').collect::<Vec<&str>>() {
-                if !part.is_empty() {
-                    base = base.and(warp::path(part.to_string()).boxed()).boxed();
+        let (state, report) = (manifest.clone(), self.watch.report.clone());
+        let dashboard = warp::path(DASHBOARD_ENDPOINT)
+            .and(warp::path::end())
+            .map(move || {
+                let html = match report.lock() {
+                    Ok(report) => dashboard::render(&state, &report),
+                    Err(e) => Err(anyhow!("failed to read build report: {e}")),
+                };
+
+                match html {
+                    Ok(html) => warp::reply::html(html).into_response(),
+                    Err(e) => warp::reply::with_status(
+                        format!("{e:#}"),
+                        warp::http::StatusCode::INTERNAL_SERVER_ERROR,
+                    )
+                    .into_response(),
                 }
-            }
+            });
+        let rules = Rules {
+            out: manifest.out.clone(),
+            base: manifest.base.clone(),
+            set: self.watch.rules.clone(),
+        };
+        let files = Files {
+            out: manifest.out.clone(),
+            compress: !self.no_compress,
+            cache_control: self.cache_control.clone(),
+        };
 
-            base.and(warp::fs::dir(manifest.out.clone())).boxed()
+        let mut base = warp::any().boxed();
+        for part in manifest.base.split('/').collect::<Vec<&str>>() {
+            if !part.is_empty() {
+                base = base.and(warp::path(part.to_string()).boxed()).boxed();
+            }
         }
-        .or(livereload);
 
-        let service = warp::serve(sonata).run((self.address, port));
+        let static_files = base.and(
+            files
+                .filter()
+                .or(warp::fs::dir(manifest.out.clone())
+                    .map(|file: warp::fs::File| file.into_response()))
+                .unify(),
+        );
+
+        let sonata = warp::path::full()
+            .and(
+                rules
+                    .redirects()
+                    .or(static_files)
+                    .unify()
+                    .or(livereload)
+                    .unify()
+                    .or(editor::routes(
+                        manifest.posts.clone(),
+                        self.editor,
+                        self.editor_hosts(),
+                    ))
+                    .unify()
+                    .or(dashboard)
+                    .unify()
+                    .or(files.not_found())
+                    .unify(),
+            )
+            .map(move |path: FullPath, reply| rules.headers(path.as_str(), reply));
+
         Runtime::new()?.block_on(async {
-            tracing::info!("listening on http://{}:{} ...", self.address, port);
-            let watcher = tokio::task::spawn_blocking(move || watcher.watch(manifest, tx));
+            let service: BoxFuture<'_, ()> = match &address {
+                Address::Unix(_) if self.https => {
+                    return Err(anyhow!(
+                        "https is not supported on unix sockets, terminate TLS at the reverse proxy instead"
+                    ));
+                }
+                Address::Unix(path) => {
+                    let incoming = unix::incoming(path)?;
+                    tracing::info!("listening on unix:{} ...", path.display());
+                    warp::serve(sonata).run_incoming(incoming).boxed()
+                }
+                _ if self.https => {
+                    let identity = self.identity(&address)?;
+                    let (addr, service) = warp::serve(sonata)
+                        .tls()
+                        .cert(identity.cert)
+                        .key(identity.key)
+                        .try_bind_with_graceful_shutdown(self.pick(&address)?, futures::future::pending())?;
+                    tracing::info!("listening on https://{addr} ...");
+                    tracing::info!("dashboard on https://{addr}/{DASHBOARD_ENDPOINT}");
+                    service.boxed()
+                }
+                _ => {
+                    let addr = self.pick(&address)?;
+                    tracing::info!("listening on http://{addr} ...");
+                    tracing::info!("dashboard on http://{addr}/{DASHBOARD_ENDPOINT}");
+                    warp::serve(sonata).run(addr).boxed()
+                }
+            };
 
+            let watcher = tokio::task::spawn_blocking(move || watcher.watch(manifest, tx));
             if let Err(e) = futures::select! {
                 r = service.fuse() => Ok(r),
                 r = watcher.fuse() => r.map_err(Into::into).and_then(|r| r),
             } {
                 tracing::error!("failed to run server: {}", e);
             }
-        });
-        Ok(())
+
+            Ok::<_, anyhow::Error>(())
+        })
+    }
+}
+
+/// The address to listen on.
+#[derive(Clone, Debug)]
+pub enum Address {
+    /// An IPv4 or IPv6 address.
+    Ip(IpAddr),
+    /// A hostname, resolved when binding.
+    Host(String),
+    /// The path of an unix socket.
+    Unix(PathBuf),
+}
+
+impl Address {
+    /// If the address is only reachable from the local machine.
+    pub fn is_local(&self) -> bool {
+        match self {
+            Self::Ip(ip) => ip.is_loopback(),
+            Self::Host(host) => host == "localhost",
+            Self::Unix(_) => false,
+        }
     }
 }
+
+impl FromStr for Address {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(anyhow!("empty path of unix socket"));
+            }
+
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        if let Ok(ip) = s.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(Self::Ip(ip));
+        }
+
+        if s.is_empty()
+            || !s
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            return Err(anyhow!("invalid address {s}"));
+        }
+
+        Ok(Self::Host(s.into()))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(ip) => write!(f, "{ip}"),
+            Self::Host(host) => write!(f, "{host}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    //! Unix socket support for the server.
+
+    use anyhow::Result;
+    use futures::{stream, Stream};
+    use std::{fs, io, os::unix::fs::FileTypeExt, path::Path};
+    use tokio::net::{UnixListener, UnixStream};
+
+    /// Bind the unix socket and get the stream of the incoming connections.
+    ///
+    /// Stale socket file from the previous run will be removed.
+    pub fn incoming(path: &Path) -> Result<impl Stream<Item = io::Result<UnixStream>>> {
+        if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        Ok(stream::unfold(listener, |listener| async move {
+            let stream = listener.accept().await.map(|(stream, _)| stream);
+            Some((stream, listener))
+        }))
+    }
+}
+
+#[cfg(not(unix))]
+mod unix {
+    //! Unix socket support for the server.
+
+    use anyhow::{anyhow, Result};
+    use futures::stream::Empty;
+    use std::{io, path::Path};
+    use tokio::net::TcpStream;
+
+    /// Unix sockets are not supported on this platform.
+    pub fn incoming(path: &Path) -> Result<Empty<io::Result<TcpStream>>> {
+        Err(anyhow!(
+            "unix socket {path:?} is not supported on this platform"
+        ))
+    }
+}
+
+#[test]
+fn address() {
+    assert!(matches!("::".parse(), Ok(Address::Ip(IpAddr::V6(_)))));
+    assert!(matches!("[::1]".parse(), Ok(Address::Ip(IpAddr::V6(_)))));
+    assert!(matches!("0.0.0.0".parse(), Ok(Address::Ip(IpAddr::V4(_)))));
+    assert!(matches!("localhost".parse(), Ok(Address::Host(_))));
+    assert!(matches!(
+        "unix:/tmp/sonata.sock".parse(),
+        Ok(Address::Unix(_))
+    ));
+    assert!("unix:".parse::<Address>().is_err());
+    assert!("http://localhost".parse::<Address>().is_err());
+}
@@ -0,0 +1,78 @@
+//! TLS identity of the server.
+
+use crate::{cmd::Address, utils::Read};
+use anyhow::{anyhow, Result};
+use std::{fs, net::IpAddr, path::Path};
+
+/// The certificate and the private key in PEM format.
+pub struct Identity {
+    /// The certificate chain.
+    pub cert: Vec<u8>,
+    /// The private key.
+    pub key: Vec<u8>,
+}
+
+impl Identity {
+    /// Load the certificate and the private key from files.
+    pub fn load(cert: &Path, key: &Path) -> Result<Self> {
+        Ok(Self {
+            cert: cert.read()?.into_bytes(),
+            key: key.read()?.into_bytes(),
+        })
+    }
+
+    /// Load the cached self-signed certificate for the address, a new
+    /// certificate will be generated if the names of it have changed.
+    pub fn self_signed(cache: &Path, address: &Address) -> Result<Self> {
+        let names = names(address)?;
+        let (cert, key, index) = (
+            cache.join("cert.pem"),
+            cache.join("key.pem"),
+            cache.join("names"),
+        );
+
+        if cert.exists() && key.exists() && index.read().ok() == Some(names.join("\n")) {
+            tracing::debug!("loading cached certificate from {cache:?}");
+            return Self::load(&cert, &key);
+        }
+
+        tracing::info!("generating self-signed certificate for {names:?} ...");
+        let certified = rcgen::generate_simple_self_signed(names.clone())
+            .map_err(|e| anyhow!("Failed to generate certificate: {e}"))?;
+
+        fs::create_dir_all(cache)?;
+        fs::write(&cert, certified.cert.pem())?;
+        fs::write(&key, certified.key_pair.serialize_pem())?;
+        fs::write(&index, names.join("\n"))?;
+        Self::load(&cert, &key)
+    }
+}
+
+/// The subject alternative names of the certificate.
+///
+/// Includes `localhost`, the bind address and the addresses of the
+/// network interfaces, so the site could be visited from LAN devices.
+fn names(address: &Address) -> Result<Vec<String>> {
+    let mut names = vec!["localhost".to_string()];
+    match address {
+        Address::Ip(ip) if !ip.is_unspecified() => names.push(ip.to_string()),
+        Address::Host(host) => names.push(host.clone()),
+        _ => {}
+    }
+
+    for interface in if_addrs::get_if_addrs()? {
+        let ip = interface.ip();
+        let link_local = match ip {
+            IpAddr::V4(ip) => ip.is_link_local(),
+            IpAddr::V6(ip) => (ip.segments()[0] & 0xffc0) == 0xfe80,
+        };
+
+        if !link_local {
+            names.push(ip.to_string());
+        }
+    }
+
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
@@ -1,17 +1,25 @@
 //! command new
 
-use crate::{App, Manifest};
+use crate::{
+    cmd::{rules::RuleSet, Report},
+    App, Manifest,
+};
 use anyhow::Result;
 use ccli::{clap, clap::Parser};
 use notify::{
-    event::{Event, EventKind, MetadataKind, ModifyKind},
-    Watcher,
+    event::{Event, EventKind, ModifyKind},
+    PollWatcher, RecursiveMode, Watcher,
 };
 use std::{
-    path::PathBuf,
-    sync::mpsc::{self, Sender},
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 /// Watch command
@@ -24,13 +32,31 @@ pub struct Watch {
     /// The output directory
     #[clap(short, long, default_value = "out")]
     pub out: PathBuf,
+
+    /// The debounce window of file events in milliseconds
+    #[clap(long, default_value = "100")]
+    pub debounce: u64,
+
+    /// Poll file changes every interval in milliseconds instead of
+    /// using the native watcher, useful for network or container
+    /// filesystems
+    #[clap(long, num_args = 0..=1, default_missing_value = "1000")]
+    pub poll: Option<u64>,
+
+    /// The report of the last build
+    #[clap(skip)]
+    pub report: Arc<Mutex<Report>>,
+
+    /// The redirect and header rules of the last build
+    #[clap(skip)]
+    pub rules: Arc<RwLock<RuleSet>>,
 }
 
 impl Watch {
     /// Get the manifest.
     pub fn manifest(&self) -> Result<Manifest> {
         let mut manifest = Manifest::load(&self.dir)?;
-        if self.out.is_absolute() || self.out != PathBuf::from("out") {
+        if self.out.is_absolute() || self.out != Path::new("out") {
             manifest.out = self.out.clone();
         }
 
@@ -38,10 +64,18 @@ impl Watch {
     }
 
     /// Watch the given directory.
-    pub fn watch(&self, manifest: Manifest, tx: Sender<Event>) -> Result<()> {
+    ///
+    /// Events are batched inside the debounce window, each batch
+    /// triggers one conditional render and one message to `tx`.
+    pub fn watch(&self, manifest: Manifest, tx: Sender<Vec<PathBuf>>) -> Result<()> {
         let mut app: App<'_> = manifest.try_into()?;
         app.livereload();
-        app.render()?;
+
+        let started = Instant::now();
+        let result = app.render();
+        self.record(Vec::new(), started, &result);
+        result?;
+        self.reload(&app.manifest.out);
 
         tracing::info!(
             "watching {} -> {} ...",
@@ -49,35 +83,95 @@ impl Watch {
             app.manifest.out.display()
         );
 
-        let paths = app.manifest.paths();
-        let mut watcher =
-            notify::recommended_watcher(move |res: notify::Result<Event>| match res {
+        let (etx, erx) = mpsc::channel::<Event>();
+        let handler = move |res: notify::Result<Event>| match res {
+            Ok(event) => {
+                tracing::trace!("event: {:#?}", event);
+                if let Err(e) = etx.send(event) {
+                    tracing::error!("send event failed: {:?}", e);
+                }
+            }
+            Err(e) => tracing::error!("watch error: {:?}", e),
+        };
+
+        let mut watcher: Box<dyn Watcher> = if let Some(interval) = self.poll {
+            tracing::info!("polling file changes every {interval}ms ...");
+            Box::new(PollWatcher::new(
+                handler,
+                notify::Config::default().with_poll_interval(Duration::from_millis(interval)),
+            )?)
+        } else {
+            if let Some(fs) = remote_fs(&self.dir) {
+                tracing::warn!(
+                    "{} is on {fs}, changes from the host or remote side may not be picked up, try `--poll`",
+                    self.dir.display()
+                );
+            }
+
+            Box::new(notify::recommended_watcher(handler)?)
+        };
+
+        for path in app.manifest.paths() {
+            if !path.exists() {
+                continue;
+            }
+            watcher.watch(&path, RecursiveMode::Recursive)?;
+        }
+
+        let debounce = Duration::from_millis(self.debounce);
+        let mut batch = BTreeSet::new();
+        loop {
+            let event = if batch.is_empty() {
+                erx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            } else {
+                erx.recv_timeout(debounce)
+            };
+
+            match event {
                 Ok(event) => {
-                    tracing::trace!("event: {:#?}", event);
-                    if event.kind == EventKind::Modify(ModifyKind::Metadata(MetadataKind::Any)) {
-                        return;
+                    if event.kind.is_access()
+                        || matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)))
+                    {
+                        continue;
                     }
 
-                    if let Err(e) = app.crender(event.paths.clone()) {
+                    batch.extend(event.paths.into_iter().filter(|p| !ignored(p)));
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let paths = std::mem::take(&mut batch).into_iter().collect::<Vec<_>>();
+                    tracing::debug!("rendering batch: {paths:?}");
+                    let started = Instant::now();
+                    let result = app.crender(paths.clone());
+                    self.record(paths.clone(), started, &result);
+                    if let Err(e) = result {
                         tracing::error!("conditional render failed: {:?}", e);
                     }
+                    self.reload(&app.manifest.out);
 
-                    if let Err(e) = tx.send(event) {
+                    if let Err(e) = tx.send(paths) {
                         tracing::error!("send tx failed: {:?}", e);
                     }
                 }
-                Err(e) => tracing::error!("watch error: {:?}", e),
-            })?;
-
-        for path in paths {
-            if !path.exists() {
-                continue;
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow::anyhow!("file watcher disconnected"));
+                }
             }
-            watcher.watch(&path, notify::RecursiveMode::Recursive)?;
         }
+    }
+
+    /// Record the result of a build to the report.
+    fn record(&self, paths: Vec<PathBuf>, started: Instant, result: &Result<()>) {
+        match self.report.lock() {
+            Ok(mut report) => report.record(paths, started, result),
+            Err(e) => tracing::error!("failed to record build: {:?}", e),
+        }
+    }
 
-        loop {
-            thread::sleep(Duration::from_secs(u64::MAX));
+    /// Reload the rules of serve from the output directory.
+    fn reload(&self, out: &Path) {
+        match self.rules.write() {
+            Ok(mut rules) => *rules = RuleSet::load(out),
+            Err(e) => tracing::error!("failed to reload rules: {:?}", e),
         }
     }
 
@@ -85,7 +179,7 @@ impl Watch {
     pub fn run(&self) -> Result<()> {
         let manifest = self.manifest()?;
 
-        let (tx, rx) = mpsc::channel::<Event>();
+        let (tx, rx) = mpsc::channel::<Vec<PathBuf>>();
         thread::spawn(move || loop {
             if let Err(e) = rx.recv() {
                 tracing::error!("watch failed: {:?}", e);
@@ -95,3 +189,91 @@ impl Watch {
         self.watch(manifest, tx)
     }
 }
+
+/// The filesystem types without the native events of the changes made
+/// on the host or remote side, e.g. the bind mounts of Docker Desktop.
+const REMOTE_FS: [&str; 8] = [
+    "nfs", "nfs4", "cifs", "smb3", "smbfs", "9p", "virtiofs", "vboxsf",
+];
+
+/// The remote filesystem type of the directory, from the mount of it
+/// in `/proc/self/mountinfo`, only detected on linux.
+fn remote_fs(dir: &Path) -> Option<String> {
+    let dir = fs::canonicalize(dir).ok()?;
+    let mountinfo = fs::read_to_string("/proc/self/mountinfo").ok()?;
+    mount_fs(&mountinfo, &dir)
+        .filter(|fs| REMOTE_FS.contains(&fs.as_str()) || fs == "fuse" || fs.starts_with("fuse."))
+}
+
+/// The filesystem type of the deepest mount point of the path.
+fn mount_fs(mountinfo: &str, path: &Path) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let (mount, fs) = line.split_once(" - ")?;
+            let point = unescape(mount.split(' ').nth(4)?);
+            let fs = fs.split(' ').next()?;
+            path.starts_with(&point)
+                .then(|| (point.components().count(), fs.to_string()))
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, fs)| fs)
+}
+
+/// Unescape the octal escapes of the mount points, e.g. `\040`.
+fn unescape(s: &str) -> PathBuf {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let code = chars.as_str().get(..3).filter(|_| c == '\\');
+        match code.and_then(|code| u8::from_str_radix(code, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                chars.nth(2);
+            }
+            None => out.push(c),
+        }
+    }
+
+    out.into()
+}
+
+/// If the path is a temporary file of editors.
+///
+/// - vim: `.foo.swp`, `.foo.swx`, `foo~` and the `4913` probe file.
+/// - emacs: `#foo#` and the `.#foo` lock file.
+fn ignored(path: &Path) -> bool {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy()) else {
+        return false;
+    };
+
+    name == "4913"
+        || name.ends_with('~')
+        || name.starts_with(".#")
+        || (name.starts_with('#') && name.ends_with('#'))
+        || [".swp", ".swx", ".swo", ".tmp"]
+            .iter()
+            .any(|ext| name.ends_with(ext))
+}
+
+#[test]
+fn mounts() {
+    let mountinfo = "\
+22 1 8:1 / / rw - ext4 /dev/sda1 rw
+30 22 0:40 / /host/my\\040site rw - fuse.grpcfuse grpcfuse rw
+31 22 0:41 / /mnt/nfs rw - nfs4 server:/export rw";
+
+    let fs = |path: &str| mount_fs(mountinfo, Path::new(path));
+    assert_eq!(fs("/home/me/site").as_deref(), Some("ext4"));
+    assert_eq!(fs("/host/my site/posts").as_deref(), Some("fuse.grpcfuse"));
+    assert_eq!(fs("/mnt/nfs/site").as_deref(), Some("nfs4"));
+}
+
+#[test]
+fn editor_temp_files() {
+    for temp in [".post.md.swp", "post.md~", "4913", ".#post.md", "#post.md#"] {
+        assert!(ignored(Path::new(temp)), "{temp}");
+    }
+
+    assert!(!ignored(Path::new("2024-01-01-post.md")));
+}
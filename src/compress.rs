@@ -0,0 +1,219 @@
+//! Gzip and brotli compression of the output.
+//!
+//! With `precompress = true`, the compressible output files get `.gz`
+//! and `.br` siblings for the static hosts serving them directly.
+//!
+//! ```toml
+//! precompress = true
+//! compress_level = 9          # 0-11, gzip is capped at 9.
+//! compress_threshold = 1024   # the smaller files are skipped.
+//! ```
+
+use anyhow::Result;
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Content encodings supported by the server, in preference order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Brotli.
+    Brotli,
+    /// Gzip.
+    Gzip,
+}
+
+impl Encoding {
+    /// All the encodings, in preference order.
+    pub const ALL: [Self; 2] = [Self::Brotli, Self::Gzip];
+
+    /// The name of the encoding in headers.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gzip",
+        }
+    }
+
+    /// The extension of the precompressed file.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Brotli => "br",
+            Self::Gzip => "gz",
+        }
+    }
+
+    /// The precompressed sibling of the file.
+    pub fn sibling(&self, file: &Path) -> PathBuf {
+        PathBuf::from(format!("{}.{}", file.display(), self.extension()))
+    }
+
+    /// Negotiate the encoding from `Accept-Encoding`.
+    pub fn negotiate(accept: &str) -> Vec<Self> {
+        let accepted = |name: &str| {
+            accept.split(',').any(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let coding = parts.next().unwrap_or_default();
+                let q = parts
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+
+                (coding == name || coding == "*") && q > 0.0
+            })
+        };
+
+        Self::ALL
+            .into_iter()
+            .filter(|e| accepted(e.name()))
+            .collect()
+    }
+
+    /// Compress the bytes with the level, 0-11 for brotli and 0-9 for gzip.
+    pub fn compress(&self, bytes: &[u8], level: u32) -> Result<Vec<u8>> {
+        match self {
+            Self::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut writer =
+                        brotli::CompressorWriter::new(&mut out, 4096, level.min(11), 22);
+                    writer.write_all(bytes)?;
+                }
+                Ok(out)
+            }
+            Self::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::new(level.min(9)),
+                );
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// If the mime type is worth compressing.
+pub fn compressible(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || [
+            "application/javascript",
+            "application/json",
+            "application/wasm",
+            "application/xml",
+            "image/svg+xml",
+        ]
+        .contains(&mime)
+}
+
+/// If the mime type of the path is worth compressing.
+fn compressible_path(path: &Path) -> bool {
+    compressible(
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .essence_str(),
+    )
+}
+
+/// Write the precompressed siblings of the compressible files in the
+/// directory recursively, up-to-date siblings are kept, siblings of
+/// the files below the threshold, larger than the file or without the
+/// file are removed.
+///
+/// Only the siblings of compressible files are touched, so archives
+/// like `archive.tar.gz` are kept as they are.
+pub fn precompress(dir: &Path, level: u32, threshold: u64) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            precompress(&path, level, threshold)?;
+            continue;
+        }
+
+        // remove the siblings of the removed files.
+        if Encoding::ALL
+            .iter()
+            .any(|e| path.extension().is_some_and(|ext| ext == e.extension()))
+        {
+            let original = path.with_extension("");
+            if compressible_path(&original) && !original.exists() {
+                fs::remove_file(&path)?;
+            }
+            continue;
+        }
+
+        if !compressible_path(&path) {
+            continue;
+        }
+
+        let metadata = fs::metadata(&path)?;
+        if metadata.len() < threshold {
+            for encoding in Encoding::ALL {
+                let sibling = encoding.sibling(&path);
+                if sibling.exists() {
+                    fs::remove_file(sibling)?;
+                }
+            }
+            continue;
+        }
+
+        let bytes = fs::read(&path)?;
+        for encoding in Encoding::ALL {
+            let sibling = encoding.sibling(&path);
+            if fs::metadata(&sibling)
+                .and_then(|s| Ok(s.modified()? >= metadata.modified()?))
+                .unwrap_or(false)
+            {
+                continue;
+            }
+
+            let compressed = encoding.compress(&bytes, level)?;
+            if compressed.len() < bytes.len() {
+                tracing::debug!("compressing {sibling:?} ...");
+                fs::write(sibling, compressed)?;
+            } else if sibling.exists() {
+                fs::remove_file(sibling)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn stale_siblings() -> Result<()> {
+    let dir = std::env::temp_dir().join("sonata-precompress-siblings");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("archive.tar.gz"), "archive")?;
+    fs::write(dir.join("removed.txt.gz"), "stale")?;
+    fs::write(dir.join("big.txt"), "big ".repeat(1024))?;
+
+    precompress(&dir, 9, 1024)?;
+    assert!(dir.join("archive.tar.gz").exists());
+    assert!(!dir.join("removed.txt.gz").exists());
+    assert!(dir.join("big.txt.gz").exists());
+
+    // the siblings of the shrunk files are removed.
+    fs::write(dir.join("big.txt"), "small")?;
+    precompress(&dir, 9, 1024)?;
+    assert!(!dir.join("big.txt.gz").exists());
+    assert!(!dir.join("big.txt.br").exists());
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn negotiate() {
+    use Encoding::*;
+
+    assert_eq!(Encoding::negotiate("gzip, deflate, br"), vec![Brotli, Gzip]);
+    assert_eq!(Encoding::negotiate("gzip;q=0.8, br;q=0"), vec![Gzip]);
+    assert_eq!(Encoding::negotiate("*"), vec![Brotli, Gzip]);
+    assert!(Encoding::negotiate("identity").is_empty());
+}
@@ -0,0 +1,193 @@
+//! The css pipeline of the theme.
+//!
+//! Stylesheets are bundled with their `@import`s, prefixed for the
+//! browser targets and minified in production builds, the source maps
+//! are written next to them as `<name>.map`.
+//!
+//! grass does not track the positions of the SCSS and Sass sources, so
+//! their source maps point to the css compiled from them.
+//!
+//! ```toml
+//! # the queries of browserslist, e.g. `defaults` or `> 0.5%, not dead`.
+//! browserslist = ["last 2 versions", "not dead", "safari >= 14.1"]
+//! ```
+
+use anyhow::{anyhow, Result};
+use lightningcss::{
+    bundler::{Bundler, FileProvider, ResolveResult, SourceProvider},
+    error::Error,
+    stylesheet::{MinifyOptions, ParserOptions, PrinterOptions},
+    targets::{Browsers, Targets},
+};
+use parcel_sourcemap::SourceMap;
+use std::{
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The css pipeline.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    /// The browser targets for the vendor prefixes.
+    pub targets: Targets,
+    /// Whether to minify the output.
+    pub minify: bool,
+}
+
+impl Pipeline {
+    /// Create the pipeline with the browserslist queries.
+    pub fn new(browserslist: &[String], minify: bool) -> Result<Self> {
+        Ok(Self {
+            targets: browsers(browserslist)?.into(),
+            minify,
+        })
+    }
+
+    /// Process the stylesheet, `source` is the content of `path`, the
+    /// imports are read from the file system.
+    pub fn process(&self, path: &Path, source: String) -> Result<Stylesheet> {
+        let provider = Sources {
+            entry: path.to_path_buf(),
+            source,
+            files: FileProvider::new(),
+        };
+
+        // the sources of the map are relative to the entry.
+        let root = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+        let mut map = SourceMap::new(&root);
+        let mut stylesheet = Bundler::new(&provider, Some(&mut map), ParserOptions::default())
+            .bundle(path)
+            .map_err(error)?;
+        stylesheet
+            .minify(MinifyOptions {
+                targets: self.targets,
+                ..Default::default()
+            })
+            .map_err(error)?;
+
+        let css = stylesheet
+            .to_css(PrinterOptions {
+                minify: self.minify,
+                source_map: Some(&mut map),
+                targets: self.targets,
+                ..Default::default()
+            })
+            .map_err(error)?;
+
+        Ok(Stylesheet {
+            code: css.code,
+            map: map
+                .to_json(None)
+                .map_err(|e| anyhow!("Failed to write the source map: {e:?}"))?,
+        })
+    }
+}
+
+/// A processed stylesheet.
+#[derive(Debug, Default)]
+pub struct Stylesheet {
+    /// The css.
+    pub code: String,
+    /// The source map of the css.
+    pub map: String,
+}
+
+impl Stylesheet {
+    /// Write the stylesheet and its source map to the output directory.
+    pub fn write(&self, out: &Path, name: &str) -> Result<()> {
+        let map = format!("{name}.map");
+        let url = Path::new(&map)
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+
+        fs::write(
+            out.join(name),
+            format!("{}\n/*# sourceMappingURL={url} */\n", self.code.trim_end()),
+        )?;
+        fs::write(out.join(&map), &self.map)?;
+        Ok(())
+    }
+}
+
+/// The sources of the bundler, the entry is provided in memory since it
+/// could be compiled from sass.
+struct Sources {
+    /// The path of the entry stylesheet.
+    entry: PathBuf,
+    /// The content of the entry stylesheet.
+    source: String,
+    /// The imported files.
+    files: FileProvider,
+}
+
+impl SourceProvider for Sources {
+    type Error = io::Error;
+
+    fn read<'a>(&'a self, file: &Path) -> Result<&'a str, Self::Error> {
+        if file == self.entry {
+            Ok(&self.source)
+        } else {
+            self.files.read(file)
+        }
+    }
+
+    fn resolve(
+        &self,
+        specifier: &str,
+        originating_file: &Path,
+    ) -> Result<ResolveResult, Self::Error> {
+        self.files.resolve(specifier, originating_file)
+    }
+}
+
+/// Format the css error with the file and line.
+fn error<T: Display>(e: Error<T>) -> anyhow::Error {
+    match e.loc {
+        Some(loc) => anyhow!(
+            "{}:{}:{}: {}",
+            loc.filename,
+            loc.line + 1,
+            loc.column,
+            e.kind
+        ),
+        None => anyhow!("{}", e.kind),
+    }
+}
+
+/// Resolve the browserslist queries into the browser targets.
+fn browsers(queries: &[String]) -> Result<Option<Browsers>> {
+    if queries.is_empty() {
+        return Ok(None);
+    }
+
+    Browsers::from_browserslist(queries)
+        .map_err(|e| anyhow!("Invalid browserslist {queries:?}: {e}"))
+}
+
+#[test]
+fn pipeline() -> Result<()> {
+    let browsers = browsers(&["safari >= 14.1".into(), "chrome 100".into()])?.unwrap();
+    assert_eq!(browsers.safari, Some(14 << 16 | 1 << 8));
+    assert_eq!(browsers.chrome, Some(100 << 16));
+    assert!(
+        self::browsers(&["> 0.5%".into(), "last 2 versions".into(), "not dead".into()])?.is_some()
+    );
+    assert!(self::browsers(&["netscape 4".into()]).is_err());
+
+    let pipeline = Pipeline::new(&["safari 12".into()], true)?;
+    let css = pipeline.process(
+        Path::new("theme.css"),
+        "a {\n  user-select: none;\n}\n".into(),
+    )?;
+    assert_eq!(css.code, "a{-webkit-user-select:none;user-select:none}");
+    assert!(css.map.contains("\"sources\":[\"theme.css\"]"));
+    assert!(!css.map.contains("\"mappings\":\"\""));
+
+    let err = pipeline
+        .process(Path::new("theme.css"), "a {}\n}}{ b {".into())
+        .unwrap_err();
+    assert!(err.to_string().starts_with("theme.css:2:"));
+    Ok(())
+}
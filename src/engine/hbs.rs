@@ -0,0 +1,70 @@
+//! The handlebars engine.
+
+use crate::{engine::TemplateEngine, helpers, Templates};
+use anyhow::Result;
+use handlebars::Handlebars;
+use serde_json::Value;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The handlebars engine, the default engine of sonata.
+#[derive(Clone, Debug)]
+pub struct HandlebarsEngine<'reg> {
+    /// The base URL of the site.
+    pub base: String,
+    /// The handlebars registry.
+    pub registry: Handlebars<'reg>,
+}
+
+impl HandlebarsEngine<'_> {
+    /// Create the engine with the embedded templates and the builtin
+    /// helpers.
+    pub fn new(base: &str) -> Result<Self> {
+        Ok(Self {
+            base: base.into(),
+            registry: registry(base)?,
+        })
+    }
+}
+
+/// Create the handlebars registry with the embedded templates and the
+/// builtin helpers.
+fn registry<'reg>(base: &str) -> Result<Handlebars<'reg>> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_prevent_indent(true);
+    handlebars.set_strict_mode(true);
+    handlebars.register_embed_templates_with_extension::<Templates>(".hbs")?;
+    helpers::register(&mut handlebars, base);
+    Ok(handlebars)
+}
+
+impl TemplateEngine for HandlebarsEngine<'_> {
+    fn register_templates(&mut self, dirs: &[PathBuf]) -> Result<()> {
+        self.registry.clear_templates();
+        self.registry
+            .register_embed_templates_with_extension::<Templates>(".hbs")?;
+        for dir in dirs.iter().filter(|dir| dir.exists()) {
+            self.registry
+                .register_templates_directory(dir, Default::default())?;
+        }
+
+        Ok(())
+    }
+
+    /// Handlebars could not unregister helpers, the registry is rebuilt
+    /// so removed scripts are dropped.
+    fn register_helpers(&mut self, dir: &Path) -> Result<()> {
+        let mut registry = registry(&self.base)?;
+        helpers::register_scripts(&mut registry, dir)?;
+        self.registry = registry;
+        Ok(())
+    }
+
+    fn render(&self, template: &str, data: &Value, writer: &mut dyn Write) -> Result<()> {
+        self.registry
+            .render_to_write(template, data, writer)
+            .map_err(Into::into)
+    }
+}
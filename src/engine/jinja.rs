@@ -0,0 +1,154 @@
+//! The jinja engine powered by minijinja.
+//!
+//! Besides the builtin filters of minijinja, the templates could use:
+//!
+//! ```jinja
+//! <a href="{{ url_for("/") }}">home</a>
+//! <link rel="stylesheet" href="{{ asset_url("theme.css") }}">
+//! <link rel="stylesheet" href="{{ asset("theme.css") }}">
+//! {{ post.date | date("%Y/%m/%d") }}
+//! {{ post.title | slugify }}
+//! {{ post.description | truncate(80) }}
+//! {{ description | markdown }}
+//! ```
+
+use crate::{
+    engine::TemplateEngine,
+    helpers,
+    utils::{self, Read},
+    Templates,
+};
+use anyhow::{anyhow, Result};
+use minijinja::{
+    escape_formatter, AutoEscape, Environment, Error, ErrorKind, State, UndefinedBehavior, Value,
+};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The extension of the jinja templates.
+const EXTENSION: &str = ".jinja";
+
+/// The jinja engine.
+#[derive(Clone, Debug)]
+pub struct JinjaEngine {
+    /// The minijinja environment.
+    pub env: Environment<'static>,
+}
+
+impl JinjaEngine {
+    /// Create the engine with the embedded templates and the builtin
+    /// functions and filters.
+    pub fn new(base: &str) -> Self {
+        let mut env = Environment::new();
+        env.set_undefined_behavior(UndefinedBehavior::SemiStrict);
+        env.set_auto_escape_callback(|_| AutoEscape::Html);
+        env.set_formatter(|out, state, value| match value.as_str() {
+            Some(s) if state.auto_escape() == AutoEscape::Html && !value.is_safe() => {
+                Ok(out.write_str(&escape(s))?)
+            }
+            _ => escape_formatter(out, state, value),
+        });
+
+        let url = |base: String| move |path: &str| utils::url(&base, path);
+        env.add_function("url_for", url(base.into()));
+        env.add_function("asset_url", url(base.into()));
+        let base = base.to_string();
+        env.add_function("asset", move |state: &State, name: &str| {
+            let data = serde_json::json!({ "assets": state.lookup("assets") });
+            utils::url(&base, helpers::asset(&data, name))
+        });
+        env.add_filter("date", |date: &str, pattern: Option<&str>| {
+            helpers::format_date(date, pattern.unwrap_or(helpers::DATE_FORMAT))
+                .map_err(|e| Error::new(ErrorKind::InvalidOperation, e.to_string()))
+        });
+        env.add_filter("slugify", |s: &str| helpers::slug(s));
+        env.add_filter("truncate", |s: &str, len: usize| helpers::truncated(s, len));
+        env.add_filter("markdown", |s: &str| {
+            Value::from_safe_string(utils::markdown(s))
+        });
+
+        Self { env }
+    }
+
+    /// Add the templates in the directory recursively, templates are
+    /// named by their paths relative to the root without the extension.
+    fn add_templates(&mut self, root: &Path, dir: &Path) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.add_templates(root, &path)?;
+                continue;
+            }
+
+            let name = path
+                .strip_prefix(root)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            if let Some(name) = name.strip_suffix(EXTENSION) {
+                self.env
+                    .add_template_owned(name.to_string(), path.read()?)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Escape the html like handlebars, minijinja escapes `/` as well
+/// which makes the links hard to read.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#x27;")
+}
+
+impl TemplateEngine for JinjaEngine {
+    fn register_templates(&mut self, dirs: &[PathBuf]) -> Result<()> {
+        self.env.clear_templates();
+        for file in Templates::iter() {
+            let Some(name) = file.strip_suffix(EXTENSION) else {
+                continue;
+            };
+
+            let template = Templates::get(&file)
+                .ok_or_else(|| anyhow!("Could not find embedded template {file}"))?;
+            self.env.add_template_owned(
+                name.to_string(),
+                String::from_utf8(template.data.into_owned())?,
+            )?;
+        }
+
+        for dir in dirs.iter().filter(|dir| dir.exists()) {
+            self.add_templates(dir, dir)?;
+        }
+
+        Ok(())
+    }
+
+    /// Script helpers are written for handlebars, they are skipped with
+    /// a warning.
+    fn register_helpers(&mut self, dir: &Path) -> Result<()> {
+        if dir.exists() {
+            tracing::warn!("script helpers in {dir:?} are only supported by handlebars");
+        }
+
+        Ok(())
+    }
+
+    fn render(
+        &self,
+        template: &str,
+        data: &serde_json::Value,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        self.env
+            .get_template(template)?
+            .render_captured_to(data, writer)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+//! Template engines of the site.
+//!
+//! The engine is chosen with `engine` in `sonata.toml`, both engines
+//! render the same page data and ship with embedded default templates.
+//!
+//! ```toml
+//! engine = "jinja"   # `handlebars` by default.
+//! ```
+
+use crate::Manifest;
+use anyhow::Result;
+use dyn_clone::DynClone;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt::Debug,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "cli")]
+use ccli::{clap, clap::ValueEnum};
+
+pub use self::{hbs::HandlebarsEngine, jinja::JinjaEngine};
+
+mod hbs;
+mod jinja;
+
+/// A template engine renders the pages of the site.
+pub trait TemplateEngine: Debug + DynClone {
+    /// Register the embedded default templates and the templates in the
+    /// directories, templates in the later directories override the
+    /// earlier ones, the templates registered before are dropped.
+    fn register_templates(&mut self, dirs: &[PathBuf]) -> Result<()>;
+
+    /// Register the builtin helpers and the script helpers in the directory.
+    ///
+    /// Engines may drop the registered templates while reloading helpers.
+    fn register_helpers(&mut self, dir: &Path) -> Result<()>;
+
+    /// Render the template with the data to the writer.
+    fn render(&self, template: &str, data: &Value, writer: &mut dyn Write) -> Result<()>;
+}
+
+dyn_clone::clone_trait_object!(TemplateEngine);
+
+/// The kinds of the template engines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// Handlebars templates, `*.hbs`.
+    #[default]
+    Handlebars,
+    /// Jinja templates rendered by minijinja, `*.jinja`.
+    Jinja,
+}
+
+impl Engine {
+    /// The extension of the template files.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Handlebars => ".hbs",
+            Self::Jinja => ".jinja",
+        }
+    }
+
+    /// Create the template engine for the manifest.
+    pub fn load<'e>(&self, manifest: &Manifest) -> Result<Box<dyn TemplateEngine + 'e>> {
+        Ok(match self {
+            Self::Handlebars => Box::new(HandlebarsEngine::new(&manifest.base)?),
+            Self::Jinja => Box::new(JinjaEngine::new(&manifest.base)),
+        })
+    }
+}
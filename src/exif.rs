@@ -0,0 +1,216 @@
+//! Lossless removal of the image metadata.
+//!
+//! The EXIF, XMP, IPTC and the comments of jpeg and png files are
+//! dropped without decoding the pixels, the ICC profile is kept and the
+//! EXIF orientation is written back as the only tag.
+
+use flate2::Crc;
+
+/// The signature of png files.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The EXIF tag of the orientation.
+const ORIENTATION: u16 = 0x0112;
+
+/// Strip the metadata of the jpeg, returns nothing if it is malformed.
+pub fn jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes.get(..2).filter(|soi| *soi == [0xff, 0xd8])?.to_vec();
+    let (mut i, mut orientation, mut exif_at) = (2, None, 2);
+    loop {
+        if *bytes.get(i)? != 0xff {
+            return None;
+        }
+
+        let marker = *bytes.get(i + 1)?;
+        match marker {
+            // fill bytes.
+            0xff => i += 1,
+            // the scan and the end of the image are copied as they are.
+            0xda | 0xd9 => break,
+            0x01 | 0xd0..=0xd7 => {
+                out.extend_from_slice(&bytes[i..i + 2]);
+                i += 2;
+            }
+            _ => {
+                let len = u16::from_be_bytes([*bytes.get(i + 2)?, *bytes.get(i + 3)?]);
+                let segment = bytes.get(i..i + 2 + usize::from(len))?;
+                let data = segment.get(4..)?;
+                let keep = match marker {
+                    0xe1 => {
+                        if let Some(tiff) = data.strip_prefix(b"Exif\0\0") {
+                            orientation = orientation.or(self::orientation(tiff));
+                        }
+                        false
+                    }
+                    0xe2 => data.starts_with(b"ICC_PROFILE\0"),
+                    // JFIF and the color transform of Adobe.
+                    0xe0 | 0xee => true,
+                    0xe3..=0xef | 0xfe => false,
+                    _ => true,
+                };
+
+                if keep {
+                    out.extend_from_slice(segment);
+                    if marker == 0xe0 && exif_at == 2 {
+                        exif_at = out.len();
+                    }
+                }
+                i += segment.len();
+            }
+        }
+    }
+
+    if let Some(orientation) = orientation {
+        let exif = [&b"Exif\0\0"[..], &tiff(orientation)].concat();
+        let len = (exif.len() as u16 + 2).to_be_bytes();
+        let app1 = [&[0xff, 0xe1][..], &len, &exif].concat();
+        out.splice(exif_at..exif_at, app1);
+    }
+
+    out.extend_from_slice(&bytes[i..]);
+    Some(out)
+}
+
+/// Strip the metadata of the png, returns nothing if it is malformed.
+pub fn png(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut out = bytes.get(..8).filter(|s| *s == PNG_SIGNATURE)?.to_vec();
+    let (mut i, mut orientation) = (8, None);
+    while i < bytes.len() {
+        let len = u32::from_be_bytes(bytes.get(i..i + 4)?.try_into().ok()?) as usize;
+        let chunk = bytes.get(i..i + 12 + len)?;
+        let (ty, data) = (&chunk[4..8], &chunk[8..8 + len]);
+        i += chunk.len();
+
+        match ty {
+            b"eXIf" => orientation = orientation.or(self::orientation(data)),
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {}
+            _ => {
+                // the orientation goes before the image data.
+                if ty == b"IDAT" {
+                    if let Some(orientation) = orientation.take() {
+                        out.extend_from_slice(&png_chunk(b"eXIf", &tiff(orientation)));
+                    }
+                }
+                out.extend_from_slice(chunk);
+            }
+        }
+    }
+
+    Some(out)
+}
+
+/// The png chunk of the type and the data.
+fn png_chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut crc = Crc::new();
+    crc.update(ty);
+    crc.update(data);
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        ty,
+        data,
+        &crc.sum().to_be_bytes(),
+    ]
+    .concat()
+}
+
+/// The orientation of the TIFF structure of the EXIF, if it transforms.
+fn orientation(tiff: &[u8]) -> Option<u16> {
+    let be = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at: usize| {
+        let b = tiff.get(at..at + 2)?;
+        Some(if be {
+            u16::from_be_bytes([b[0], b[1]])
+        } else {
+            u16::from_le_bytes([b[0], b[1]])
+        })
+    };
+    let u32_at = |at: usize| {
+        let b = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(if be {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    (0..usize::from(u16_at(ifd)?))
+        .map(|n| ifd + 2 + n * 12)
+        .find(|entry| u16_at(*entry) == Some(ORIENTATION))
+        .and_then(|entry| u16_at(entry + 8))
+        .filter(|orientation| (2..=8).contains(orientation))
+}
+
+/// The TIFF structure with the orientation as the only tag.
+fn tiff(orientation: u16) -> Vec<u8> {
+    [
+        &b"MM\0*\0\0\0\x08\0\x01"[..],
+        &ORIENTATION.to_be_bytes(),
+        b"\0\x03\0\0\0\x01",
+        &orientation.to_be_bytes(),
+        b"\0\0\0\0\0\0",
+    ]
+    .concat()
+}
+
+#[test]
+fn strip() {
+    let exif = |orientation: u16| {
+        let mut exif = [&b"Exif\0\0"[..], &tiff(orientation)].concat();
+        exif.extend_from_slice(b"GPSLatitude 48.8584");
+        exif
+    };
+    let segment = |marker: u8, data: &[u8]| {
+        let len = (data.len() as u16 + 2).to_be_bytes();
+        [&[0xff, marker][..], &len, data].concat()
+    };
+
+    let scan = b"\xff\xda\0\x02scan\xff\xd9";
+    let source = [
+        &[0xff, 0xd8][..],
+        &segment(0xe0, b"JFIF\0"),
+        &segment(0xe1, &exif(6)),
+        &segment(0xe2, b"ICC_PROFILE\0icc"),
+        &segment(0xfe, b"comment"),
+        scan,
+    ]
+    .concat();
+    let stripped = jpeg(&source).unwrap();
+    assert_eq!(
+        stripped,
+        [
+            &[0xff, 0xd8][..],
+            &segment(0xe0, b"JFIF\0"),
+            &segment(0xe1, &[&b"Exif\0\0"[..], &tiff(6)].concat()),
+            &segment(0xe2, b"ICC_PROFILE\0icc"),
+            scan,
+        ]
+        .concat()
+    );
+
+    let source = [
+        PNG_SIGNATURE,
+        &png_chunk(b"IHDR", b"header"),
+        &png_chunk(b"eXIf", &exif(1)[6..]),
+        &png_chunk(b"tEXt", b"GPS\0here"),
+        &png_chunk(b"IDAT", b"data"),
+        &png_chunk(b"IEND", b""),
+    ]
+    .concat();
+    assert_eq!(
+        png(&source).unwrap(),
+        [
+            PNG_SIGNATURE,
+            &png_chunk(b"IHDR", b"header"),
+            &png_chunk(b"IDAT", b"data"),
+            &png_chunk(b"IEND", b""),
+        ]
+        .concat()
+    );
+
+    assert!(jpeg(b"png").is_none());
+}
@@ -0,0 +1,462 @@
+//! Handlebars helpers of the templates.
+//!
+//! Besides the builtin helpers of handlebars (`eq`, `ne`, `not`, `and`,
+//! `or`, `len`, ...), the templates could use:
+//!
+//! | helper      | example                                        |
+//! |-------------|------------------------------------------------|
+//! | `url_for`   | `{{ url_for "/" }}`                            |
+//! | `asset_url` | `{{ asset_url "theme.css" }}`                  |
+//! | `asset`     | `{{ asset "theme.css" }}`, fingerprinted       |
+//! | `date`      | `{{ date post.date "%Y/%m/%d" }}`              |
+//! | `slugify`   | `{{ slugify post.title }}`                     |
+//! | `truncate`  | `{{ truncate post.description 80 }}`           |
+//! | `join`      | `{{ join post.labels ", " }}`                  |
+//! | `json`      | `{{{ json post }}}`                            |
+//! | `markdown`  | `{{{ markdown description }}}`                 |
+//! | `lt`        | `{{#if (lt post.date "2024-01-01") }}`         |
+//! | `take`      | `{{#each (take posts 5) }}`                    |
+//! | `where`     | `{{#each (where posts "labels" "rust") }}`     |
+//!
+//! `lt` overrides the builtin one, which only compares integers, to
+//! compare floats and strings as well, e.g. the dates of the posts.
+//!
+//! Site-specific helpers could be written in [rhai](https://rhai.rs) as
+//! `helpers/<name>.rhai`, the parameters and the hash of the helper are
+//! available as `params` and `hash`, the last expression is the result.
+//!
+//! ```rhai
+//! // helpers/percent.rhai, {{ percent 0.34 label="%" }}
+//! (params[0] * 100).to_string() + hash["label"]
+//! ```
+
+use crate::utils::{self, Read};
+use anyhow::{anyhow, Result};
+use chrono::{format::StrftimeItems, DateTime, NaiveDate, NaiveDateTime};
+use handlebars::{
+    handlebars_helper, Context, Handlebars, Helper, HelperDef, RenderContext, RenderError,
+    RenderErrorReason, ScopedJson,
+};
+use rhai::{
+    serde::{from_dynamic, to_dynamic},
+    Dynamic, Scope, AST,
+};
+use serde_json::Value;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    fs,
+    path::Path,
+};
+
+/// The default pattern of the `date` helper.
+pub const DATE_FORMAT: &str = "%b. %d, %Y";
+
+/// Register the helpers to the handlebars instance.
+pub fn register(handlebars: &mut Handlebars, base: &str) {
+    let url = Url { base: base.into() };
+
+    handlebars.register_helper("url_for", Box::new(url.clone()));
+    handlebars.register_helper("asset_url", Box::new(url.clone()));
+    handlebars.register_helper("asset", Box::new(Asset(url)));
+    handlebars.register_helper("date", Box::new(Date));
+    handlebars.register_helper("slugify", Box::new(slugify));
+    handlebars.register_helper("truncate", Box::new(truncate));
+    handlebars.register_helper("join", Box::new(join));
+    handlebars.register_helper("json", Box::new(json));
+    handlebars.register_helper("markdown", Box::new(markdown));
+    handlebars.register_helper("lt", Box::new(lt));
+    handlebars.register_helper("take", Box::new(take));
+    handlebars.register_helper("where", Box::new(filter));
+}
+
+/// Register the rhai scripts in the directory as helpers.
+pub fn register_scripts(handlebars: &mut Handlebars, dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "rhai") {
+            continue;
+        }
+
+        let name = path.with_extension("").file_name()?;
+        tracing::debug!("registering helper {name} from {path:?} ...");
+        let script = Script::compile(handlebars, &name, &path.read()?)?;
+        handlebars.register_helper(&name, Box::new(script));
+    }
+
+    Ok(())
+}
+
+/// Get the string parameter of the helper.
+fn param<'a>(h: &'a Helper, name: &'static str, index: usize) -> Result<&'a str, RenderError> {
+    h.param(index)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, index))?
+        .value()
+        .as_str()
+        .ok_or_else(|| RenderErrorReason::InvalidParamType("string").into())
+}
+
+/// Join the path to the base URL of the site.
+#[derive(Clone, Debug)]
+pub struct Url {
+    /// The base URL of the site.
+    pub base: String,
+}
+
+impl HelperDef for Url {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let path = param(h, "url_for", 0)?;
+        Ok(ScopedJson::Derived(Value::String(utils::url(
+            &self.base, path,
+        ))))
+    }
+}
+
+/// Resolve the asset to its fingerprinted URL with the `assets` map of
+/// the page data, unknown assets fall back to `asset_url`.
+#[derive(Clone, Debug)]
+pub struct Asset(pub Url);
+
+impl HelperDef for Asset {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let name = param(h, "asset", 0)?;
+        Ok(ScopedJson::Derived(Value::String(utils::url(
+            &self.0.base,
+            asset(ctx.data(), name),
+        ))))
+    }
+}
+
+/// The fingerprinted name of the asset in the `assets` map of the data.
+pub fn asset<'a>(data: &'a Value, name: &'a str) -> &'a str {
+    let name = name.trim_start_matches('/');
+    data.get("assets")
+        .and_then(|assets| assets.get(name))
+        .and_then(Value::as_str)
+        .unwrap_or(name)
+}
+
+/// Format a date or a datetime with the chrono pattern.
+pub struct Date;
+
+impl HelperDef for Date {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let date = param(h, "date", 0)?;
+        let pattern = match h.param(1) {
+            Some(_) => param(h, "date", 1)?,
+            None => DATE_FORMAT,
+        };
+
+        let output = format_date(date, pattern)
+            .map_err(|e| RenderErrorReason::Other(format!("date: {e}")))?;
+        Ok(ScopedJson::Derived(Value::String(output)))
+    }
+}
+
+/// Format a date, a datetime or an RFC 3339 datetime with the chrono
+/// pattern.
+pub fn format_date(date: &str, pattern: &str) -> Result<String> {
+    let items = StrftimeItems::new(pattern);
+    let formatted = if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        datetime.format_with_items(items)
+    } else if let Ok(datetime) = date.parse::<NaiveDateTime>() {
+        datetime.and_utc().fixed_offset().format_with_items(items)
+    } else if let Ok(date) = date.parse::<NaiveDate>() {
+        date.and_time(Default::default())
+            .and_utc()
+            .fixed_offset()
+            .format_with_items(items)
+    } else {
+        return Err(anyhow!("invalid date {date:?}"));
+    };
+
+    let mut output = String::new();
+    write!(output, "{formatted}").map_err(|_| anyhow!("invalid pattern {pattern:?}"))?;
+    Ok(output)
+}
+
+/// Turn the string into a lowercase, dash separated slug.
+pub fn slug(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Truncate the string to `len` characters with an ellipsis.
+pub fn truncated(s: &str, len: usize) -> String {
+    if s.chars().count() > len {
+        format!("{}…", s.chars().take(len).collect::<String>().trim_end())
+    } else {
+        s.to_string()
+    }
+}
+
+/// A helper defined by a rhai script.
+pub struct Script {
+    /// The name of the helper.
+    pub name: String,
+    /// The compiled script.
+    pub ast: AST,
+}
+
+impl Script {
+    /// Compile the script with the rhai engine of handlebars.
+    pub fn compile(handlebars: &Handlebars, name: &str, script: &str) -> Result<Self> {
+        let ast = handlebars
+            .engine()
+            .compile(script)
+            .map_err(|e| anyhow!("Failed to compile helper {name}: {e}"))?;
+
+        Ok(Self {
+            name: name.into(),
+            ast,
+        })
+    }
+}
+
+impl HelperDef for Script {
+    fn call_inner<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        r: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+    ) -> Result<ScopedJson<'rc>, RenderError> {
+        let error =
+            |e: &dyn Display| RenderErrorReason::Other(format!("helper {}: {e}", self.name));
+        let params = h.params().iter().map(|p| p.value()).collect::<Vec<_>>();
+        let hash = h
+            .hash()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.value()))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut scope = Scope::new();
+        scope.push_dynamic("params", to_dynamic(params).map_err(|e| error(&e))?);
+        scope.push_dynamic("hash", to_dynamic(hash).map_err(|e| error(&e))?);
+
+        let result = r
+            .engine()
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| error(&e))?;
+
+        Ok(ScopedJson::Derived(
+            from_dynamic(&result).map_err(|e| error(&e))?,
+        ))
+    }
+}
+
+handlebars_helper!(slugify: |s: str| slug(s));
+
+handlebars_helper!(truncate: |s: str, len: u64| truncated(s, len as usize));
+
+handlebars_helper!(join: |items: array, sep: str| {
+    items
+        .iter()
+        .map(|item| item.as_str().map(Into::into).unwrap_or_else(|| item.to_string()))
+        .collect::<Vec<String>>()
+        .join(sep)
+});
+
+handlebars_helper!(json: |value: Json| value.to_string());
+
+handlebars_helper!(markdown: |s: str| utils::markdown(s));
+
+handlebars_helper!(lt: |x: Json, y: Json| {
+    let ordering = match (x, y) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64().partial_cmp(&y.as_f64()),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    };
+
+    ordering == Some(Ordering::Less)
+});
+
+handlebars_helper!(take: |items: array, n: u64| {
+    items.iter().take(n as usize).cloned().collect::<Vec<_>>()
+});
+
+handlebars_helper!(filter: |items: array, key: str, value: Json| {
+    items
+        .iter()
+        .filter(|item| match item.get(key) {
+            Some(Value::Array(field)) if !value.is_array() => field.contains(value),
+            Some(field) => field == value,
+            None => false,
+        })
+        .cloned()
+        .collect::<Vec<_>>()
+});
+
+#[cfg(test)]
+fn render(template: &str) -> Result<String, RenderError> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(true);
+    register(&mut handlebars, "/sub/");
+
+    handlebars.render_template(
+        template,
+        &serde_json::json!({
+            "favicon": "/favicon.svg",
+            "assets": { "theme.css": "theme.e3b0c442.css" },
+            "labels": ["rust", "sonata"],
+            "posts": [
+                { "title": "c", "labels": ["rust"], "author": "a" },
+                { "title": "b", "labels": ["sonata"], "author": "b" },
+                { "title": "a", "labels": ["rust", "sonata"], "author": "a" },
+            ],
+        }),
+    )
+}
+
+#[test]
+fn url() -> anyhow::Result<()> {
+    assert_eq!(render(r#"{{ url_for "/" }}"#)?, "/sub/");
+    assert_eq!(
+        render(r#"{{ url_for "posts/a.html" }}"#)?,
+        "/sub/posts/a.html"
+    );
+    assert_eq!(render("{{ asset_url favicon }}")?, "/sub/favicon.svg");
+    assert_eq!(
+        render(r#"{{ url_for "https://x.com/a" }}"#)?,
+        "https://x.com/a"
+    );
+    assert!(render("{{ url_for 1 }}").is_err());
+    assert_eq!(
+        render(r#"{{ asset "/theme.css" }}"#)?,
+        "/sub/theme.e3b0c442.css"
+    );
+    assert_eq!(render("{{ asset favicon }}")?, "/sub/favicon.svg");
+    Ok(())
+}
+
+#[test]
+fn date() -> anyhow::Result<()> {
+    assert_eq!(render(r#"{{ date "2024-01-02" }}"#)?, "Jan. 02, 2024");
+    assert_eq!(
+        render(r#"{{ date "2024-01-02" "%Y/%m/%d" }}"#)?,
+        "2024/01/02"
+    );
+    assert_eq!(
+        render(r#"{{ date "2024-01-02T03:04:05" "%H:%M" }}"#)?,
+        "03:04"
+    );
+    assert_eq!(
+        render(r#"{{ date "2024-01-02T03:04:05+08:00" "%z" }}"#)?,
+        "+0800"
+    );
+    assert!(render(r#"{{ date "yesterday" }}"#).is_err());
+    assert!(render(r#"{{ date "2024-01-02" "%Q" }}"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn strings() -> anyhow::Result<()> {
+    assert_eq!(render(r#"{{ slugify " Hello, World! " }}"#)?, "hello-world");
+    assert_eq!(render(r#"{{ truncate "hello world" 6 }}"#)?, "hello…");
+    assert_eq!(render(r#"{{ truncate "hello" 6 }}"#)?, "hello");
+    assert_eq!(render(r#"{{ join labels ", " }}"#)?, "rust, sonata");
+    assert_eq!(render("{{{ json labels }}}")?, r#"["rust","sonata"]"#);
+    assert_eq!(
+        render(r#"{{{ markdown "**hi**" }}}"#)?,
+        "<p><strong>hi</strong></p>\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn conditions() -> anyhow::Result<()> {
+    let check = |condition: &str| {
+        render(&format!(
+            "{{{{#if {condition} }}}}y{{{{else}}}}n{{{{/if}}}}"
+        ))
+    };
+
+    assert_eq!(check(r#"(eq "a" "a")"#)?, "y");
+    assert_eq!(check("(lt 1 2.5)")?, "y");
+    assert_eq!(check(r#"(lt "2024-01-01" "2023-12-31")"#)?, "n");
+    assert_eq!(check(r#"(lt 1 "2")"#)?, "n");
+    assert_eq!(check(r#"(and 1 "a")"#)?, "y");
+    assert_eq!(check(r#"(and 1 "")"#)?, "n");
+    assert_eq!(check(r#"(or 0 "a")"#)?, "y");
+    assert_eq!(check("(or 0 null)")?, "n");
+    Ok(())
+}
+
+#[test]
+fn arrays() -> anyhow::Result<()> {
+    assert_eq!(
+        render("{{#each (take posts 2) }}{{ title }}{{/each}}")?,
+        "cb"
+    );
+    assert_eq!(
+        render(r#"{{#each (where posts "labels" "sonata") }}{{ title }}{{/each}}"#)?,
+        "ba"
+    );
+    assert_eq!(
+        render(r#"{{#each (where posts "author" "a") }}{{ title }}{{/each}}"#)?,
+        "ca"
+    );
+    Ok(())
+}
+
+#[test]
+fn script() -> Result<()> {
+    let mut handlebars = Handlebars::new();
+    let script = Script::compile(
+        &handlebars,
+        "percent",
+        r#"(params[0] * 100).to_string() + hash["label"]"#,
+    )?;
+    handlebars.register_helper("percent", Box::new(script));
+    assert_eq!(
+        handlebars.render_template(r#"{{ percent 0.5 label="%" }}"#, &())?,
+        "50.0%"
+    );
+
+    let script = Script::compile(&handlebars, "fail", "let a = 1;\nthrow \"oops\";")?;
+    handlebars.register_helper("fail", Box::new(script));
+    let error = handlebars
+        .render_template("{{ fail }}", &())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("helper fail") && error.contains("line 2"),
+        "{error}"
+    );
+
+    let error = Script::compile(&handlebars, "broken", "let a = ;")
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(
+        error.contains("helper broken") && error.contains("line 1"),
+        "{error}"
+    );
+    Ok(())
+}
@@ -0,0 +1,344 @@
+//! Responsive images of the posts.
+//!
+//! With `image_widths`, the markdown images under the public directory
+//! are resized to the widths, converted to the formats and rendered as
+//! `<picture>` with `srcset`. The variants are decoded and encoded again
+//! so the EXIF and GPS metadata are dropped, they are named by the
+//! content hash of the source and reused in the later builds.
+//!
+//! The jpeg and png originals of the public directory and the page
+//! bundles are copied without their metadata, the pixels are kept as
+//! they are.
+//!
+//! WebP is encoded lossless, which is usually larger than the jpeg
+//! fallback for photos, it is an opt-in for graphics and screenshots.
+//!
+//! ```toml
+//! image_widths = [480, 960, 1600]
+//! image_formats = ["avif"]   # the default formats, or with "webp".
+//! image_quality = 80         # for avif and jpeg.
+//! ```
+
+use crate::{exif, helpers, utils};
+use anyhow::{anyhow, Result};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageReader,
+};
+use pulldown_cmark::escape::escape_html;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "cli")]
+use ccli::{clap, clap::ValueEnum};
+
+/// The directory of the image variants in the output directory.
+pub const IMAGES_DIR: &str = "images";
+
+/// The formats of the image variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// AVIF.
+    Avif,
+    /// Lossless WebP, usually larger than the jpeg fallback for photos.
+    Webp,
+    /// JPEG, the fallback of the opaque images.
+    Jpeg,
+    /// PNG, the fallback of the transparent images.
+    Png,
+}
+
+impl Format {
+    /// The format of the jpeg and png sources.
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    /// The extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Avif => "avif",
+            Self::Webp => "webp",
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+        }
+    }
+
+    /// The mime type of the format.
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Avif => "image/avif",
+            Self::Webp => "image/webp",
+            Self::Jpeg => "image/jpeg",
+            Self::Png => "image/png",
+        }
+    }
+
+    /// Encode the image to the path.
+    fn encode(&self, image: &DynamicImage, path: &Path, quality: u8) -> Result<()> {
+        let image = if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        };
+
+        let w = BufWriter::new(File::create(path)?);
+        match self {
+            Self::Avif => {
+                image.write_with_encoder(AvifEncoder::new_with_speed_quality(w, 8, quality))?
+            }
+            Self::Webp => image.write_with_encoder(WebPEncoder::new_lossless(w))?,
+            Self::Jpeg => {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+                    .write_with_encoder(JpegEncoder::new_with_quality(w, quality))?;
+            }
+            Self::Png => image.write_with_encoder(PngEncoder::new(w))?,
+        }
+
+        Ok(())
+    }
+}
+
+/// The image pipeline.
+#[derive(Clone, Debug)]
+pub struct Images {
+    /// The base URL of the site.
+    pub base: String,
+    /// The public directory, linked as `/public/..`.
+    pub public: PathBuf,
+    /// The output directory.
+    pub out: PathBuf,
+    /// The widths of the variants.
+    pub widths: Vec<u32>,
+    /// The formats of the variants besides the fallback.
+    pub formats: Vec<Format>,
+    /// The quality of the lossy formats.
+    pub quality: u8,
+    /// The directory of the page bundle, relative images are resolved in it.
+    pub bundle: Option<PathBuf>,
+}
+
+impl Images {
+    /// Render the image as `<picture>`, returns nothing if the image is
+    /// not a jpeg or png in the public directory or the page bundle.
+    pub fn picture(&self, src: &str, alt: &str, title: &str) -> Result<Option<String>> {
+        let Some(path) = self.source(src) else {
+            return Ok(None);
+        };
+
+        let Some(fallback) = Format::of(&path) else {
+            return Ok(None);
+        };
+
+        let error = |e: image::ImageError| anyhow!("Failed to load image {}: {e}", path.display());
+        let mut decoder = ImageReader::open(&path)?
+            .with_guessed_format()?
+            .into_decoder()
+            .map_err(error)?;
+        let orientation = decoder.orientation().map_err(error)?;
+        let (mut width, mut height) = decoder.dimensions();
+        if matches!(
+            orientation,
+            image::metadata::Orientation::Rotate90
+                | image::metadata::Orientation::Rotate270
+                | image::metadata::Orientation::Rotate90FlipH
+                | image::metadata::Orientation::Rotate270FlipH
+        ) {
+            (width, height) = (height, width);
+        }
+
+        let mut widths = self
+            .widths
+            .iter()
+            .copied()
+            .filter(|w| *w < width)
+            .collect::<Vec<_>>();
+        widths.push(width.min(self.widths.iter().copied().max().unwrap_or(width)));
+        widths.sort();
+        widths.dedup();
+
+        // the variants are named by the content hash of the source.
+        let bytes = fs::read(&path)?;
+        let hash = Sha256::digest(&bytes)
+            .iter()
+            .take(4)
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let stem = path
+            .file_stem()
+            .map(|s| helpers::slug(&s.to_string_lossy()))
+            .unwrap_or_default();
+
+        let dir = self.out.join(IMAGES_DIR);
+        fs::create_dir_all(&dir)?;
+
+        let max = *widths.last().unwrap_or(&width);
+        let sizes = format!("(max-width: {max}px) 100vw, {max}px");
+        let (mut html, mut img) = (String::from("<picture>"), String::new());
+        let mut image = None::<DynamicImage>;
+        let formats = self.formats.iter().filter(|f| **f != fallback);
+        for format in formats.chain(Some(&fallback)) {
+            let mut srcset = Vec::new();
+            for w in &widths {
+                let name = format!("{stem}-{hash}-{w}.{}", format.extension());
+                let output = dir.join(&name);
+                if !output.exists() {
+                    let image = match &mut image {
+                        Some(image) => image,
+                        None => {
+                            let mut decoded = DynamicImage::from_decoder(
+                                ImageReader::open(&path)?
+                                    .with_guessed_format()?
+                                    .into_decoder()
+                                    .map_err(error)?,
+                            )
+                            .map_err(error)?;
+                            decoded.apply_orientation(orientation);
+                            image.insert(decoded)
+                        }
+                    };
+
+                    tracing::debug!("writing image {output:?} ...");
+                    format.encode(
+                        &image.resize_exact(*w, scale(height, *w, width), FilterType::Lanczos3),
+                        &output,
+                        self.quality,
+                    )?;
+                }
+
+                img = utils::url(&self.base, &format!("{IMAGES_DIR}/{name}"));
+                srcset.push(format!("{img} {w}w"));
+            }
+
+            if *format != fallback {
+                html.push_str(&format!(
+                    "<source type=\"{}\" srcset=\"{}\" sizes=\"{sizes}\">",
+                    format.mime(),
+                    srcset.join(", ")
+                ));
+                continue;
+            }
+
+            html.push_str(&format!(
+                "<img src=\"{img}\" srcset=\"{}\" sizes=\"{sizes}\" width=\"{max}\" height=\"{}\" alt=\"{}\"",
+                srcset.join(", "),
+                scale(height, max, width),
+                escape(alt)?
+            ));
+        }
+
+        if !title.is_empty() {
+            html.push_str(&format!(" title=\"{}\"", escape(title)?));
+        }
+        html.push_str(" loading=\"lazy\" decoding=\"async\"></picture>");
+
+        Ok(Some(html))
+    }
+
+    /// Copy the file to the target, the metadata of the jpeg and png
+    /// originals is stripped losslessly.
+    pub fn copy(&self, path: &Path, target: &Path) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let bytes = fs::read(path)?;
+        let stripped = match Format::of(path) {
+            Some(Format::Jpeg) => exif::jpeg(&bytes),
+            Some(Format::Png) => exif::png(&bytes),
+            _ => {
+                fs::write(target, bytes)?;
+                return Ok(());
+            }
+        };
+
+        match stripped {
+            Some(stripped) => fs::write(target, stripped)?,
+            None => {
+                tracing::warn!("copying malformed image {path:?} as it is");
+                fs::write(target, bytes)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The file of the image in the public directory or the page bundle.
+    fn source(&self, src: &str) -> Option<PathBuf> {
+        if src.contains("..") {
+            return None;
+        }
+
+        if let Some(bundle) = self
+            .bundle
+            .as_ref()
+            .filter(|_| !src.starts_with(['/', '#']) && !src.contains(':'))
+        {
+            let path = bundle.join(src);
+            return path.is_file().then_some(path);
+        }
+
+        let base = self.base.trim_matches('/');
+        let src = src.trim_start_matches('/');
+        let src = src
+            .strip_prefix(base)
+            .filter(|_| !base.is_empty())
+            .unwrap_or(src)
+            .trim_start_matches('/');
+
+        let path = self.public.join(src.strip_prefix("public/")?);
+        path.is_file().then_some(path)
+    }
+}
+
+/// Scale the length by `to / from`.
+fn scale(len: u32, to: u32, from: u32) -> u32 {
+    (u64::from(len) * u64::from(to) / u64::from(from)).max(1) as u32
+}
+
+/// Escape the attribute value.
+fn escape(s: &str) -> Result<String> {
+    let mut escaped = String::new();
+    escape_html(&mut escaped, s)?;
+    Ok(escaped)
+}
+
+#[test]
+fn picture() -> Result<()> {
+    let root = std::env::temp_dir().join("sonata-images");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("public"))?;
+    DynamicImage::new_rgb8(40, 20).save(root.join("public/My Photo.jpg"))?;
+
+    let images = Images {
+        base: "/sub/".into(),
+        public: root.join("public"),
+        out: root.join("out"),
+        widths: vec![16, 64],
+        formats: vec![Format::Avif, Format::Webp],
+        quality: 80,
+        bundle: None,
+    };
+
+    assert!(images.picture("https://x.com/a.jpg", "", "")?.is_none());
+    let html = utils::markdown_with("![a & b](</sub/public/My Photo.jpg>)", &images)?;
+    assert!(html.contains("<picture><source type=\"image/avif\" srcset=\"/sub/images/my-photo-"));
+    assert!(html.contains("-16.webp 16w, /sub/images/my-photo-"));
+    assert!(html.contains("width=\"40\" height=\"20\" alt=\"a &amp; b\""));
+    assert!(html.contains("loading=\"lazy\""));
+    assert_eq!(fs::read_dir(root.join("out/images"))?.count(), 6);
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
@@ -11,6 +11,16 @@
 //!     └── 2024-01-01-hello-world.md
 //! ```
 //!
+//! A post could also be a page bundle, the directory with `index.md`
+//! and the assets next to it, linked relatively in the markdown:
+//!
+//! ```ignore
+//! posts
+//! └── 2024-01-02-foo
+//!     ├── index.md      # ![cover](cover.png)
+//!     └── cover.png
+//! ```
+//!
 //! The full configuration:
 //!
 //! ```toml
@@ -24,13 +34,31 @@
 //! posts = "posts"           # The path to the posts.
 //! public = "public"         # The path to the public directory.
 //! templates = "templates"   # The path to the templates.
+//! helpers = "helpers"       # The path to the rhai script helpers.
+//! engine = "handlebars"     # The template engine, `handlebars` or `jinja`.
+//! browserslist = []         # The browserslist queries of the css, e.g. `> 0.5%`.
+//! fingerprint = false       # Copy assets to content-hash names, see `asset`.
+//! minify = { css = true }   # Minify `html`, `css` and `js`, or `true` for all.
+//! image_widths = []         # The widths of the responsive images, see `images`.
+//! precompress = false       # Write `.gz` and `.br` siblings of the output.
+//! compress_level = 9        # The compression level of the siblings, 0-11.
+//! compress_threshold = 1024 # The minimum size in bytes to precompress.
+//! og_image = false          # Render the Open Graph images of the posts.
+//! url = ""                  # The URL of the site for the absolute image URLs.
 //!
 //! # Theme could also be a folder:
 //! #
 //! # - [theme]
-//! #   - index.css (optional)
-//! #   - post.css  (optional)
+//! #   - index.css (optional, linked by the index page)
+//! #   - post.css  (optional, linked by the posts)
+//! #   - 404.css   (optional, `<page>.css` for any page type)
 //! #   - theme.css (optional)
+//! #
+//! # stylesheets are written with source maps, they could also be
+//! # `.scss` or `.sass` sources, partials like `_vars.scss` are only
+//! # imported,
+//! #
+//! # or a theme package with `theme.toml`, see `Theme`.
 //! theme = "theme.css"
 //! ```
 //!
@@ -39,24 +67,42 @@
 //! GPL-3.0-only
 
 mod app;
+mod assets;
 pub mod cmd;
+mod compress;
+mod css;
+pub mod engine;
+mod exif;
+mod helpers;
+pub mod images;
 mod manifest;
+mod minify;
+mod og;
 mod post;
+mod scss;
+mod theme;
 mod utils;
 
 pub use self::{
     app::{App, LIVERELOAD_ENDPOINT},
+    assets::{Assets, ASSET_MANIFEST},
     manifest::{Manifest, MINIMAL_MANIFEST},
-    post::{Meta, Post, TEMPLATE_POST},
+    minify::Minify,
+    og::{OG_DIR, OG_TEMPLATE},
+    post::{Meta, Post, BUNDLE_INDEX, TEMPLATE_POST},
+    theme::{Theme, THEME_MANIFEST},
 };
 
 /// The default sonata templates.
 #[derive(rust_embed::RustEmbed)]
 #[folder = "blog/templates"]
 #[include = "*.hbs"]
+#[include = "*.jinja"]
+#[include = "og.svg"]
 pub struct Templates;
 
 #[test]
 fn embed() {
     assert!(Templates::get("post.hbs").is_some());
+    assert!(Templates::get("post.jinja").is_some());
 }
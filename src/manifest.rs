@@ -1,13 +1,22 @@
 //! Manifest of the site.
 
 use crate::{
+    css::Pipeline,
+    engine::Engine,
+    images::{Format, Images},
+    minify::Minify,
+    og::OG_TEMPLATE,
+    post::BUNDLE_INDEX,
+    scss,
     utils::{self, Prefix, Read},
-    Post,
+    Post, Templates, Theme,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::Datelike;
 use serde::{Deserialize, Serialize};
 use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
@@ -22,6 +31,9 @@ posts = "posts"
 title = "sonata"
 "#;
 
+/// The rule files of the static hosts in the public directory.
+const HOST_RULES: [&str; 2] = ["_redirects", "_headers"];
+
 /// Manifest of the site.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[cfg_attr(feature = "cli", derive(Parser))]
@@ -40,6 +52,12 @@ pub struct Manifest {
     #[cfg_attr(feature = "cli", clap(short, long, default_value = "/"))]
     pub base: String,
 
+    /// The URL of the site, e.g. `https://example.com`, for the absolute
+    /// URLs of the Open Graph images.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long, default_value = ""))]
+    pub url: String,
+
     /// The description of the site.
     #[serde(default = "Default::default")]
     #[cfg_attr(feature = "cli", clap(short, long, default_value = ""))]
@@ -70,6 +88,16 @@ pub struct Manifest {
     #[cfg_attr(feature = "cli", clap(short, long, default_value = "templates"))]
     pub templates: PathBuf,
 
+    /// The path of the rhai script helpers.
+    #[serde(default = "default::helpers")]
+    #[cfg_attr(feature = "cli", clap(long, default_value = "helpers"))]
+    pub helpers: PathBuf,
+
+    /// The template engine.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long, value_enum, default_value = "handlebars"))]
+    pub engine: Engine,
+
     #[serde(default = "default::image")]
     #[cfg_attr(feature = "cli", clap(short, long, default_value = "ximage"))]
     pub image: String,
@@ -80,21 +108,141 @@ pub struct Manifest {
     #[serde(default = "default::theme")]
     #[cfg_attr(feature = "cli", clap(long, default_value = "theme"))]
     pub theme: PathBuf,
+
+    /// The browserslist queries of the theme stylesheets, e.g. `> 0.5%`.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long, value_delimiter = ','))]
+    pub browserslist: Vec<String>,
+
+    /// The output types to minify, `html`, `css` and `js`.
+    #[serde(default)]
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            num_args = 0..=1,
+            require_equals = true,
+            default_value = "css",
+            default_missing_value = "all"
+        )
+    )]
+    pub minify: Minify,
+
+    /// The widths of the responsive images, empty to disable them.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long, value_delimiter = ','))]
+    pub image_widths: Vec<u32>,
+
+    /// The formats of the responsive images besides the fallback.
+    #[serde(default = "default::image_formats")]
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, value_enum, value_delimiter = ',', default_value = "avif")
+    )]
+    pub image_formats: Vec<Format>,
+
+    /// The quality of the lossy responsive images, 1-100.
+    #[serde(default = "default::image_quality")]
+    #[cfg_attr(feature = "cli", clap(long, default_value = "80"))]
+    pub image_quality: u8,
+
+    /// Write the `.gz` and `.br` siblings of the compressible output.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub precompress: bool,
+
+    /// The compression level of the precompressed files, 0-11.
+    #[serde(default = "default::compress_level")]
+    #[cfg_attr(feature = "cli", clap(long, default_value = "9"))]
+    pub compress_level: u32,
+
+    /// The minimum size in bytes of the files to precompress.
+    #[serde(default = "default::compress_threshold")]
+    #[cfg_attr(feature = "cli", clap(long, default_value = "1024"))]
+    pub compress_threshold: u64,
+
+    /// Copy the theme and public assets to content-hash names.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub fingerprint: bool,
+
+    /// Render the Open Graph images of the posts from `og.svg`.
+    #[serde(default)]
+    #[cfg_attr(feature = "cli", clap(long))]
+    pub og_image: bool,
+
+    /// The theme packages, the selected theme comes first followed by
+    /// the themes it extends.
+    #[serde(skip)]
+    #[cfg_attr(feature = "cli", clap(skip))]
+    pub themes: Vec<Theme>,
 }
 
 impl Manifest {
     /// Load manifest from the provided path.
     pub fn load(root: &Path) -> Result<Self> {
+        Self::load_theme(root, None)
+    }
+
+    /// Load manifest with the overrides of the command line.
+    ///
+    /// The theme of the overrides is resolved against the project root
+    /// and its defaults are applied like the configured theme.
+    pub fn load_with(root: &Path, overrides: Manifest) -> Result<Self> {
+        let theme = (overrides.theme != default::theme()).then(|| overrides.theme.clone());
+        let mut manifest = Self::load_theme(root, theme)?;
+        manifest.merge(overrides);
+        Ok(manifest)
+    }
+
+    /// Load manifest with the theme replacing the configured one.
+    fn load_theme(root: &Path, theme: Option<PathBuf>) -> Result<Self> {
         let path = utils::find_proj(root)?;
         let toml = path.join("sonata.toml");
 
         tracing::info!("loading manifest from {toml:?}");
-        let manifest: Self = toml::from_str(&toml.read()?)
+        let mut table: toml::Table = toml::from_str(&toml.read()?)
             .map_err(|e| anyhow::anyhow!("Failed to parse {toml:?}: {e}"))?;
+        if let Some(theme) = theme {
+            table.insert("theme".into(), theme.to_string_lossy().into_owned().into());
+        }
 
+        // apply the defaults of the theme packages, the theme itself first.
+        let theme = path.join(
+            table
+                .get("theme")
+                .and_then(|theme| theme.as_str())
+                .map(PathBuf::from)
+                .unwrap_or_else(default::theme),
+        );
+        let themes = Theme::chain(&theme)?;
+        for theme in &themes {
+            for (key, value) in &theme.defaults {
+                if key != "theme" {
+                    table.entry(key).or_insert_with(|| value.clone());
+                }
+            }
+        }
+
+        let mut manifest: Self = table
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("Failed to parse {toml:?}: {e}"))?;
+        manifest.themes = themes;
         Ok(manifest.abs(path))
     }
 
+    /// Copy the static assets of the theme packages.
+    pub fn copy_assets(&self) -> Result<()> {
+        for dir in self.themes.iter().rev().map(Theme::assets) {
+            if dir.exists() {
+                tracing::debug!("copying theme assets {}", dir.display());
+                etc::cp_r(&dir, &self.out)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Copy the public directory.
     pub fn copy_public(&self) -> Result<()> {
         let public = self.out.join("public");
@@ -105,23 +253,144 @@ impl Manifest {
                 public.display()
             );
 
-            etc::cp_r(&self.public, &public)?;
+            self.copy(&self.public, &public)?;
+        }
+
+        self.copy_rules()
+    }
+
+    /// Copy the entry of the public directory, the images are copied
+    /// without their metadata if the image pipeline is enabled.
+    fn copy(&self, source: &Path, target: &Path) -> Result<()> {
+        let Some(images) = self.images() else {
+            if source.is_dir() {
+                etc::cp_r(source, target)?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(source, target)?;
+            }
+
+            return Ok(());
+        };
+
+        if source.is_dir() {
+            for file in utils::files(source)? {
+                images.copy(&source.join(&file), &target.join(&file))?;
+            }
+            Ok(())
+        } else {
+            images.copy(source, target)
+        }
+    }
+
+    /// Copy the `_redirects` and `_headers` of the public directory to
+    /// the root of the output directory, where the static hosts read them.
+    fn copy_rules(&self) -> Result<()> {
+        for name in HOST_RULES {
+            let (source, target) = (self.public.join(name), self.out.join(name));
+            if source.is_file() {
+                fs::copy(source, target)?;
+            } else if target.exists() {
+                fs::remove_file(target)?;
+            }
         }
 
         Ok(())
     }
 
+    /// Sync a single entry of the public directory.
+    ///
+    /// Copies the entry to the output directory if it exists, otherwise
+    /// removes the stale output of it.
+    pub fn sync_public(&self, path: &Path) -> Result<()> {
+        let Ok(relative) = utils::canonicalize(path)?
+            .strip_prefix(fs::canonicalize(&self.public)?)
+            .map(Path::to_path_buf)
+        else {
+            return self.copy_public();
+        };
+
+        let target = self.out.join("public").join(relative);
+        if path.exists() {
+            tracing::debug!("copying {} -> {}", path.display(), target.display());
+            self.copy(path, &target)?;
+        } else if target.is_dir() {
+            tracing::debug!("removing {}", target.display());
+            fs::remove_dir_all(&target)?;
+        } else if target.exists() {
+            tracing::debug!("removing {}", target.display());
+            fs::remove_file(&target)?;
+        }
+
+        self.copy_rules()
+    }
+
+    /// Load a post with the link within the base URL.
+    pub fn post(&self, path: impl AsRef<Path>) -> Result<Post> {
+        Ok(Post::load_with(path, self.images().as_ref())?.base(&self.base))
+    }
+
+    /// The template of the Open Graph images, the last template
+    /// directory with `og.svg` wins over the embedded one.
+    pub fn og_template(&self) -> Result<String> {
+        if let Some(path) = self
+            .template_dirs()
+            .into_iter()
+            .rev()
+            .map(|dir| dir.join(OG_TEMPLATE))
+            .find(|path| path.exists())
+        {
+            return path.read();
+        }
+
+        let template = Templates::get(OG_TEMPLATE)
+            .ok_or_else(|| anyhow!("Could not find embedded template {OG_TEMPLATE}"))?;
+        Ok(String::from_utf8(template.data.into_owned())?)
+    }
+
+    /// The absolute URL of the path, root-relative without `url`.
+    pub fn absolute(&self, path: &str) -> String {
+        let path = utils::url(&self.base, path);
+        if self.url.is_empty() {
+            return path;
+        }
+
+        utils::url(&self.url, &path)
+    }
+
+    /// The image pipeline of the posts, enabled by `image_widths`.
+    pub fn images(&self) -> Option<Images> {
+        (!self.image_widths.is_empty()).then(|| Images {
+            base: self.base.clone(),
+            public: self.public.clone(),
+            out: self.out.clone(),
+            widths: self.image_widths.clone(),
+            formats: self.image_formats.clone(),
+            quality: self.image_quality,
+            bundle: None,
+        })
+    }
+
     /// Get the posts.
     pub fn posts(&self) -> Result<Vec<Post>> {
-        let mut posts = fs::read_dir(&self.posts)?
-            .map(|e| Post::load(e?.path()))
-            .collect::<Result<Vec<_>>>()?;
+        let mut posts = Vec::new();
+        for entry in fs::read_dir(&self.posts)? {
+            let path = entry?.path();
+            // directories without `index.md` are not page bundles.
+            if path.is_dir() && !path.join(BUNDLE_INDEX).exists() {
+                continue;
+            }
+
+            posts.push(self.post(path)?);
+        }
 
         if posts.is_empty() {
             return Ok(posts);
         }
 
-        posts.sort_by(|a, b| b.meta.date.cmp(&a.meta.date));
+        posts.sort_by_key(|post| Reverse(post.meta.date));
 
         let mut current_year = posts[0].meta.date.year() + 1;
         posts.iter_mut().for_each(|post| {
@@ -135,32 +404,215 @@ impl Manifest {
         Ok(posts)
     }
 
-    /// Write styles to the given path.
-    pub fn write_theme(&self, out: &Path) -> Result<()> {
-        let base = self
+    /// The directories of the templates, the extended themes come first
+    /// and the templates of the site come last to override them.
+    pub fn template_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = self
+            .themes
+            .iter()
+            .rev()
+            .map(Theme::templates)
+            .collect::<Vec<_>>();
+        dirs.push(self.templates.clone());
+        dirs
+    }
+
+    /// The source paths of the theme files with their default contents.
+    ///
+    /// `theme` could be a theme package, a directory includes the files,
+    /// or the path of the theme stylesheet with the highlight files next
+    /// to it. Files missing in a theme package fall back to the themes it
+    /// extends, the theme stylesheet could also be `theme.scss` or
+    /// `theme.sass`.
+    pub fn theme_files(&self) -> Result<Vec<(&'static str, PathBuf, &'static str)>> {
+        if !self.themes.is_empty() {
+            return Ok([
+                ("theme.css", default::DEFAULT_THEME),
+                ("highlight.css", default::HIGHLIGHT_CSS),
+                ("highlight.js", default::HIGHLIGHT_JS),
+            ]
+            .into_iter()
+            .map(|(name, default)| {
+                let path = self
+                    .themes
+                    .iter()
+                    .flat_map(|theme| sources(&theme.path, name))
+                    .find(|path| path.exists())
+                    .unwrap_or_else(|| self.theme.join(name));
+                (name, path, default)
+            })
+            .collect());
+        }
+
+        let (dir, theme) = if self.theme.is_dir() || self.theme.extension().is_none() {
+            let theme = sources(&self.theme, "theme.css")
+                .into_iter()
+                .find(|path| path.exists())
+                .unwrap_or_else(|| self.theme.join("theme.css"));
+            (self.theme.as_path(), theme)
+        } else {
+            let dir = self.theme.parent().ok_or_else(|| {
+                anyhow::anyhow!("Could not find the parent path of {:?}", self.theme)
+            })?;
+            (dir, self.theme.clone())
+        };
+
+        Ok(vec![
+            ("theme.css", theme, default::DEFAULT_THEME),
+            (
+                "highlight.css",
+                dir.join("highlight.css"),
+                default::HIGHLIGHT_CSS,
+            ),
+            (
+                "highlight.js",
+                dir.join("highlight.js"),
+                default::HIGHLIGHT_JS,
+            ),
+        ])
+    }
+
+    /// The directories of the theme, the extended themes come first.
+    fn theme_dirs(&self) -> Vec<PathBuf> {
+        if !self.themes.is_empty() {
+            self.themes.iter().rev().map(|t| t.path.clone()).collect()
+        } else if self.theme.is_dir() {
+            vec![self.theme.clone()]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// The stylesheets of the page types in the theme directory, named by
+    /// the templates of the pages, e.g. `index.css`, `post.css` and
+    /// `404.css`, or their `.scss` and `.sass` sources. Sass partials
+    /// starting with `_` are skipped.
+    ///
+    /// In the same directory, `.css` wins over `.scss` and `.scss` wins
+    /// over `.sass`, the themes extending others win over them.
+    pub fn page_styles(&self) -> Result<BTreeMap<String, PathBuf>> {
+        let mut styles = BTreeMap::new();
+        for dir in self.theme_dirs() {
+            let mut found = BTreeMap::<String, (usize, PathBuf)>::new();
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let Some(rank) = path
+                    .extension()
+                    .and_then(|ext| scss::EXTENSIONS.iter().position(|e| ext == *e))
+                else {
+                    continue;
+                };
+
+                let page = path.with_extension("").file_name()?;
+                if page == "theme" || page == "highlight" || page.starts_with('_') {
+                    continue;
+                }
+
+                if found.get(&page).is_none_or(|(r, _)| rank < *r) {
+                    found.insert(page, (rank, path));
+                }
+            }
+
+            styles.extend(found.into_iter().map(|(page, (_, path))| (page, path)));
+        }
+
+        Ok(styles)
+    }
+
+    /// The paths of the theme and the theme packages it extends, the
+    /// sass files next to a sass theme are included for its partials.
+    pub fn theme_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = vec![self.theme.clone()];
+        paths.extend(self.themes.iter().skip(1).map(|theme| theme.path.clone()));
+        if let Some(dir) = self
             .theme
             .parent()
-            .ok_or_else(|| anyhow::anyhow!("Could not find the parent path of {:?}", self.theme))?;
-
-        for (maybe, default) in [
-            ("theme.css", default::DEFAULT_THEME),
-            ("highlight.css", default::HIGHLIGHT_CSS),
-            ("highlight.js", default::HIGHLIGHT_JS),
-        ] {
-            let path = base.join(maybe);
-            let hl = if path.exists() {
-                path.read()?
-            } else {
-                default.to_string()
+            .filter(|_| self.theme.is_file() && scss::is_sass(&self.theme))
+        {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if scss::is_sass(&path) && path != self.theme {
+                    paths.push(path);
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Write styles to the given path, sass sources are compiled with
+    /// the theme directories as load paths, then the stylesheets are
+    /// processed by the css pipeline.
+    pub fn write_theme(&self, out: &Path, minify: Minify) -> Result<()> {
+        let pipeline = Pipeline::new(&self.browserslist, minify.css)?;
+        let mut load_paths = self.theme_dirs();
+        load_paths.reverse();
+
+        let mut styles = self
+            .page_styles()?
+            .into_iter()
+            .map(|(page, path)| (format!("{page}.css"), path, None))
+            .collect::<Vec<_>>();
+        styles.extend(
+            self.theme_files()?
+                .into_iter()
+                .map(|(name, path, default)| (name.to_string(), path, Some(default))),
+        );
+
+        for (name, path, default) in styles {
+            let mut content = match default {
+                Some(default) if !path.exists() => default.into(),
+                _ => scss::compile(&path, &load_paths)?,
             };
 
-            fs::write(out.join(maybe), hl)?;
+            if name.ends_with(".css") {
+                pipeline.process(&path, content)?.write(out, &name)?;
+                continue;
+            }
+
+            if name.ends_with(".js") && minify.js {
+                content = minify.js(&path, &content)?;
+            }
+
+            fs::write(out.join(&name), content)?;
         }
 
         Ok(())
     }
 
+    /// The names of the theme files, the theme assets and the public
+    /// files relative to the output directory.
+    pub fn asset_names(&self) -> Result<Vec<String>> {
+        let mut names = self
+            .page_styles()?
+            .into_keys()
+            .map(|page| format!("{page}.css"))
+            .collect::<Vec<_>>();
+        names.extend(
+            self.theme_files()?
+                .into_iter()
+                .map(|(name, _, _)| name.to_string()),
+        );
+
+        for dir in self.themes.iter().map(Theme::assets) {
+            names.extend(utils::files(&dir)?.iter().map(|path| slash(path)));
+        }
+
+        names.extend(
+            utils::files(&self.public)?
+                .iter()
+                .map(|path| format!("public/{}", slash(path))),
+        );
+
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
     /// Merge two manifests.
+    ///
+    /// The theme is not merged since it changes the defaults of the
+    /// manifest, see [`Manifest::load_with`].
     pub fn merge(&mut self, other: Manifest) {
         if other.favicon != default::favicon() {
             self.favicon = other.favicon;
@@ -182,20 +634,74 @@ impl Manifest {
             self.templates = other.templates;
         }
 
-        if other.theme != default::theme() {
-            self.theme = other.theme;
+        if other.engine != Engine::default() {
+            self.engine = other.engine;
+        }
+
+        if other.helpers != default::helpers() {
+            self.helpers = other.helpers;
+        }
+
+        if !other.url.is_empty() {
+            self.url = other.url;
+        }
+
+        if !other.browserslist.is_empty() {
+            self.browserslist = other.browserslist;
+        }
+
+        if other.minify != Minify::default() {
+            self.minify = other.minify;
+        }
+
+        if !other.image_widths.is_empty() {
+            self.image_widths = other.image_widths;
+        }
+
+        if other.image_formats != default::image_formats() {
+            self.image_formats = other.image_formats;
+        }
+
+        if other.image_quality != default::image_quality() {
+            self.image_quality = other.image_quality;
+        }
+
+        if other.precompress {
+            self.precompress = true;
+        }
+
+        if other.compress_level != default::compress_level() {
+            self.compress_level = other.compress_level;
+        }
+
+        if other.compress_threshold != default::compress_threshold() {
+            self.compress_threshold = other.compress_threshold;
+        }
+
+        if other.fingerprint {
+            self.fingerprint = true;
+        }
+
+        if other.og_image {
+            self.og_image = true;
         }
     }
 
     /// Get all the paths.
     pub fn paths(&self) -> Vec<PathBuf> {
-        vec![
+        let mut paths = vec![
             self.favicon.clone(),
             self.posts.clone(),
             self.public.clone(),
             self.templates.clone(),
-            self.theme.clone(),
-        ]
+            self.helpers.clone(),
+        ];
+
+        paths.extend(
+            self.theme_paths()
+                .unwrap_or_else(|_| vec![self.theme.clone()]),
+        );
+        paths
     }
 
     /// Make paths absolute.
@@ -205,6 +711,7 @@ impl Manifest {
         self.posts.prefix(&prefix);
         self.public.prefix(&prefix);
         self.templates.prefix(&prefix);
+        self.helpers.prefix(&prefix);
         self.theme.prefix(&prefix);
         self
     }
@@ -216,6 +723,7 @@ impl Default for Manifest {
             site: "sonata".to_string(),
             title: "sonata".to_string(),
             base: "".to_string(),
+            url: "".to_string(),
             image: "".to_string(),
             description: "".to_string(),
             favicon: default::favicon(),
@@ -223,13 +731,46 @@ impl Default for Manifest {
             posts: default::posts(),
             public: default::public(),
             templates: default::templates(),
+            helpers: default::helpers(),
+            engine: Engine::default(),
+            browserslist: Vec::new(),
+            minify: Minify::default(),
+            image_widths: Vec::new(),
+            image_formats: default::image_formats(),
+            image_quality: default::image_quality(),
+            precompress: false,
+            compress_level: default::compress_level(),
+            compress_threshold: default::compress_threshold(),
+            fingerprint: false,
+            og_image: false,
+            themes: Vec::new(),
             theme: default::theme(),
         }
     }
 }
 
+/// The candidate sources of the stylesheet in the directory, `theme.css`
+/// could also be compiled from `theme.scss` or `theme.sass`.
+fn sources(dir: &Path, name: &str) -> Vec<PathBuf> {
+    let path = dir.join(name);
+    if path.extension().is_none_or(|ext| ext != "css") {
+        return vec![path];
+    }
+
+    scss::EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .collect()
+}
+
+/// The path with `/` separators.
+fn slash(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
 mod default {
     //! The default configurations for the manifest.
+    use crate::images::Format;
     use std::path::PathBuf;
 
     /// The pre-compiled highlight.js.
@@ -279,10 +820,35 @@ mod default {
         PathBuf::from("templates")
     }
 
+    /// Default implementation of the script helpers.
+    pub fn helpers() -> PathBuf {
+        PathBuf::from("helpers")
+    }
+
     /// Default implementation of the templates.
     pub fn theme() -> PathBuf {
         PathBuf::from("theme")
     }
+
+    /// Default implementation of the image formats.
+    pub fn image_formats() -> Vec<Format> {
+        vec![Format::Avif]
+    }
+
+    /// Default implementation of the image quality.
+    pub fn image_quality() -> u8 {
+        80
+    }
+
+    /// Default implementation of the compression level.
+    pub fn compress_level() -> u32 {
+        9
+    }
+
+    /// Default implementation of the compression threshold.
+    pub fn compress_threshold() -> u64 {
+        1024
+    }
 }
 
 #[test]
@@ -0,0 +1,166 @@
+//! Minification of the output.
+//!
+//! ```toml
+//! minify = true                                      # all the output types.
+//! minify = { html = true, css = true, js = false }   # per output type.
+//! ```
+//!
+//! Only css is minified by default, nothing is minified with livereload.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
+
+/// The output types to minify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "Config")]
+pub struct Minify {
+    /// The rendered html pages.
+    pub html: bool,
+    /// The emitted stylesheets.
+    pub css: bool,
+    /// The emitted scripts.
+    pub js: bool,
+}
+
+impl Minify {
+    /// Minify all the output types.
+    pub const ALL: Self = Self::all(true);
+
+    /// Minify nothing.
+    pub const NONE: Self = Self::all(false);
+
+    const fn all(minify: bool) -> Self {
+        Self {
+            html: minify,
+            css: minify,
+            js: minify,
+        }
+    }
+
+    /// Minify the html page, the whitespaces of `<pre>` and `<textarea>`
+    /// are kept.
+    pub fn html(&self, html: &[u8]) -> Vec<u8> {
+        minify_html::minify(
+            html,
+            &minify_html::Cfg {
+                minify_css: self.css,
+                minify_js: self.js,
+                keep_closing_tags: true,
+                keep_html_and_head_opening_tags: true,
+                ..minify_html::Cfg::spec_compliant()
+            },
+        )
+    }
+
+    /// Minify the script.
+    pub fn js(&self, path: &Path, js: &str) -> Result<String> {
+        let session = minify_js::Session::new();
+        let mut out = Vec::new();
+        minify_js::minify(
+            &session,
+            minify_js::TopLevelMode::Global,
+            js.as_bytes(),
+            &mut out,
+        )
+        .map_err(|e| anyhow!("Failed to minify {}: {e:?}", path.display()))?;
+
+        Ok(String::from_utf8(out)?)
+    }
+}
+
+impl Default for Minify {
+    fn default() -> Self {
+        Self {
+            css: true,
+            ..Self::NONE
+        }
+    }
+}
+
+/// `--minify`, `--minify=false` or `--minify=html,js`.
+impl FromStr for Minify {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true" | "all" => return Ok(Self::ALL),
+            "false" | "none" => return Ok(Self::NONE),
+            _ => {}
+        }
+
+        let mut minify = Self::NONE;
+        for ty in s.split(',').map(str::trim) {
+            match ty {
+                "html" => minify.html = true,
+                "css" => minify.css = true,
+                "js" => minify.js = true,
+                _ => {
+                    return Err(format!(
+                        "Unknown output type {ty}, expected html, css or js"
+                    ))
+                }
+            }
+        }
+
+        Ok(minify)
+    }
+}
+
+/// The minify option in `sonata.toml`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Config {
+    All(bool),
+    Types {
+        #[serde(default)]
+        html: bool,
+        #[serde(default)]
+        css: bool,
+        #[serde(default)]
+        js: bool,
+    },
+}
+
+impl From<Config> for Minify {
+    fn from(config: Config) -> Self {
+        match config {
+            Config::All(minify) => Self::all(minify),
+            Config::Types { html, css, js } => Self { html, css, js },
+        }
+    }
+}
+
+#[test]
+fn minify() -> Result<()> {
+    let html = Minify::ALL.html(
+        b"<html>\n  <body>\n    <p class=\"a  b\">  hello  </p>\n    <pre>  a\n    b</pre>\n  </body>\n</html>",
+    );
+    assert_eq!(
+        String::from_utf8(html)?,
+        "<html><body><p class=\"a b\">hello</p><pre>  a\n    b</pre></body></html>"
+    );
+
+    assert_eq!(
+        Minify::ALL.js(Path::new("a.js"), "const a = 1;\nconsole.log( a );")?,
+        "const a=1;console.log(a)"
+    );
+
+    assert_eq!(
+        "html,js".parse::<Minify>().map_err(anyhow::Error::msg)?,
+        Minify {
+            css: false,
+            ..Minify::ALL
+        }
+    );
+    assert_eq!(
+        toml::from_str::<toml::Value>("minify = { html = true }")?["minify"]
+            .clone()
+            .try_into::<Minify>()?,
+        Minify {
+            html: true,
+            ..Minify::NONE
+        }
+    );
+    Ok(())
+}
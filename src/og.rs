@@ -0,0 +1,149 @@
+//! Open Graph images of the posts.
+//!
+//! With `og_image = true`, the posts get `og/<name>.png` rendered from
+//! the `og.svg` template with the bundled DejaVu Sans fonts, the template
+//! could be overridden in the templates directory. The placeholders are
+//! `{{ site }}`, `{{ title }}`, `{{ author }}`, `{{ date }}` and
+//! `{{ byline }}`, the title is wrapped into `<tspan x="0">` lines.
+//!
+//! The `image` in the frontmatter of a post overrides the generated one.
+
+use crate::Post;
+use anyhow::{anyhow, Result};
+use resvg::{tiny_skia, usvg};
+use std::{
+    fs,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+/// The directory of the Open Graph images in the output directory.
+pub const OG_DIR: &str = "og";
+
+/// The template of the Open Graph images.
+pub const OG_TEMPLATE: &str = "og.svg";
+
+/// The bundled fonts.
+const FONTS: [&[u8]; 2] = [
+    include_bytes!("../blog/fonts/DejaVuSans.ttf"),
+    include_bytes!("../blog/fonts/DejaVuSans-Bold.ttf"),
+];
+
+/// The max characters of a title line.
+const LINE_WIDTH: usize = 24;
+
+/// The max lines of the title, the rest is truncated.
+const MAX_LINES: usize = 3;
+
+/// The font database of the bundled fonts.
+fn fontdb() -> Arc<usvg::fontdb::Database> {
+    static FONTDB: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTDB
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            for font in FONTS {
+                db.load_font_data(font.to_vec());
+            }
+            Arc::new(db)
+        })
+        .clone()
+}
+
+/// Render the Open Graph image of the post to the path.
+pub fn render(template: &str, post: &Post, site: &str, path: &Path) -> Result<()> {
+    let svg = fill(template, post, site);
+    let options = usvg::Options {
+        fontdb: fontdb(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(&svg, &options)
+        .map_err(|e| anyhow!("Failed to parse {OG_TEMPLATE}: {e}"))?;
+
+    let size = tree.size().to_int_size();
+    let mut pixmap = tiny_skia::Pixmap::new(size.width(), size.height())
+        .ok_or_else(|| anyhow!("Invalid size of {OG_TEMPLATE}: {size:?}"))?;
+    resvg::render(&tree, Default::default(), &mut pixmap.as_mut());
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    tracing::debug!("rendering {path:?} ...");
+    fs::write(path, pixmap.encode_png()?)?;
+    Ok(())
+}
+
+/// Fill the placeholders of the template with the post.
+fn fill(template: &str, post: &Post, site: &str) -> String {
+    let title = wrap(&post.meta.title)
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let dy = if i == 0 { "0" } else { "1.2em" };
+            format!("<tspan x=\"0\" dy=\"{dy}\">{}</tspan>", escape(line))
+        })
+        .collect::<String>();
+    let byline = [&post.meta.author, &post.meta.formatted_date]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" · ");
+
+    template
+        .replace("{{ site }}", &escape(site))
+        .replace("{{ title }}", &title)
+        .replace("{{ author }}", &escape(&post.meta.author))
+        .replace("{{ date }}", &escape(&post.meta.formatted_date))
+        .replace("{{ byline }}", &escape(&byline))
+}
+
+/// Wrap the title into lines by words.
+fn wrap(title: &str) -> Vec<String> {
+    let mut lines = Vec::<String>::new();
+    for word in title.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= LINE_WIDTH => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.into()),
+        }
+    }
+
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        lines[MAX_LINES - 1].push('…');
+    }
+
+    lines
+}
+
+/// Escape the text of the svg.
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn og() -> Result<()> {
+    assert_eq!(
+        wrap("The static site generator, sonata, renders Open Graph images of the posts in pure rust"),
+        [
+            "The static site",
+            "generator, sonata,",
+            "renders Open Graph…"
+        ]
+    );
+
+    let mut post = Post::default();
+    post.meta.title = "A & B".into();
+    post.meta.formatted_date = "Jan. 01, 2024".into();
+    assert_eq!(
+        fill("{{ site }}|{{ title }}|{{ byline }}", &post, "<sonata>"),
+        "&lt;sonata&gt;|<tspan x=\"0\" dy=\"0\">A &amp; B</tspan>|Jan. 01, 2024"
+    );
+    Ok(())
+}
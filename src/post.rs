@@ -1,6 +1,9 @@
 //! post layout.
 
-use crate::utils::{self, Read};
+use crate::{
+    images::Images,
+    utils::{self, Read},
+};
 use anyhow::{anyhow, Result};
 use chrono::NaiveDate;
 use colored::Colorize;
@@ -22,6 +25,9 @@ title: "Hello World!"
 This is my first post with sonata !
 "#;
 
+/// The markdown of a page bundle, a post directory with its assets.
+pub const BUNDLE_INDEX: &str = "index.md";
+
 /// Post layout with is markdown with yaml metadata.
 ///
 /// TODO: load posts from any directory.
@@ -35,22 +41,75 @@ pub struct Post {
     /// The metadata of the post.
     #[serde(flatten)]
     pub meta: Meta,
-    /// The path to the post.
+    /// The path to the post, the markdown file or the directory of the
+    /// page bundle.
     #[serde(skip)]
     pub path: PathBuf,
+    /// If the post is a page bundle.
+    #[serde(skip)]
+    pub bundle: bool,
 }
 
 impl Post {
     /// Load post from path.
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
-        let mut this: Self = path.read()?.parse()?;
-        this.path = path.as_ref().to_path_buf();
+        Self::load_with(path, None)
+    }
+
+    /// Load post from path, the images are rendered by the image
+    /// pipeline if any.
+    ///
+    /// The path could be a page bundle, e.g. `posts/2024-01-01-foo` with
+    /// `index.md` and the assets of the post, the relative images of the
+    /// bundle are resolved in it.
+    pub fn load_with(path: impl AsRef<Path>, images: Option<&Images>) -> Result<Self> {
+        let path = path.as_ref();
+        let bundle = path.is_dir();
+        let mut this = if bundle {
+            let images = images.map(|images| Images {
+                bundle: Some(path.to_path_buf()),
+                ..images.clone()
+            });
+            Self::parse(&path.join(BUNDLE_INDEX).read()?, images.as_ref())?
+        } else {
+            Self::parse(&path.read()?, images)?
+        };
+
+        this.path = path.to_path_buf();
+        this.bundle = bundle;
         this.merge_meta()
     }
 
+    /// The name of the post, `yyyy-mm-dd-title`.
+    pub fn name(&self) -> Result<String> {
+        if self.bundle {
+            self.path.file_name()
+        } else {
+            self.path.with_extension("").file_name()
+        }
+    }
+
+    /// Parse post from the markdown with yaml metadata.
+    pub fn parse(s: &str, images: Option<&Images>) -> Result<Self> {
+        let mut this = Self::default();
+        let mut content = s.to_string();
+
+        let markdown = s.splitn(3, "---").collect::<Vec<_>>();
+        if markdown.len() == 3 {
+            this.meta = markdown[1].parse::<Meta>()?;
+            content = markdown[2].to_string();
+        }
+
+        this.content = match images {
+            Some(images) => utils::markdown_with(&content, images)?,
+            None => utils::markdown(&content),
+        };
+        Ok(this)
+    }
+
     /// Merge date from the post metadata.
     pub fn merge_meta(mut self) -> Result<Self> {
-        let name = self.path.with_extension("").file_name()?;
+        let name = self.name()?;
         let meta = name.splitn(4, '-').collect::<Vec<_>>();
         if meta.len() != 4 {
             return Err(anyhow::anyhow!(
@@ -91,26 +150,50 @@ impl Post {
     /// Generate the index of the post.
     pub fn index(mut self, name: String) -> Self {
         self.index.index = self.meta.date.format("%h. %d").to_string();
-        self.index.link = format!("posts/{name}.html");
+        self.index.link = if self.bundle {
+            format!("posts/{name}/index.html")
+        } else {
+            format!("posts/{name}.html")
+        };
+        self
+    }
+
+    /// Make the link of the post absolute within the base URL.
+    pub fn base(mut self, base: &str) -> Self {
+        self.index.link = utils::url(base, &self.index.link);
         self
     }
+
+    /// The path of the rendered post relative to the output directory,
+    /// page bundles are rendered as `posts/<name>/index.html` next to
+    /// their assets.
+    pub fn output(&self) -> Result<PathBuf> {
+        let name = self.name()?;
+        Ok(if self.bundle {
+            PathBuf::from("posts").join(name).join("index.html")
+        } else {
+            PathBuf::from("posts").join(format!("{name}.html"))
+        })
+    }
+
+    /// The assets of the page bundle, relative to the bundle.
+    pub fn assets(&self) -> Result<Vec<PathBuf>> {
+        if !self.bundle {
+            return Ok(Vec::new());
+        }
+
+        Ok(utils::files(&self.path)?
+            .into_iter()
+            .filter(|path| path != Path::new(BUNDLE_INDEX))
+            .collect())
+    }
 }
 
 impl FromStr for Post {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self> {
-        let mut this = Self::default();
-        let mut content = s.to_string();
-
-        let markdown = s.splitn(3, "---").collect::<Vec<_>>();
-        if markdown.len() == 3 {
-            this.meta = markdown[1].parse::<Meta>()?;
-            content = markdown[2].to_string();
-        }
-
-        this.content = utils::markdown(&content);
-        Ok(this)
+        Self::parse(s, None)
     }
 }
 
@@ -136,6 +219,10 @@ pub struct Meta {
     /// The title of the post.
     #[serde(default)]
     pub title: String,
+    /// The Open Graph image of the post, relative to the page bundle or
+    /// the base URL, overrides the generated one.
+    #[serde(default)]
+    pub image: String,
 }
 
 impl FromStr for Meta {
@@ -0,0 +1,29 @@
+//! SCSS/Sass compilation of the theme in pure rust.
+//!
+//! Imports are resolved relative to the importing file, then from the
+//! directories of the theme packages.
+
+use crate::utils::Read;
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+
+/// The extensions of the stylesheet sources, in preference order.
+pub const EXTENSIONS: [&str; 3] = ["css", "scss", "sass"];
+
+/// If the path is a SCSS or Sass source.
+pub fn is_sass(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "scss" || ext == "sass")
+}
+
+/// Compile the stylesheet, css files are read as they are.
+pub fn compile(path: &Path, load_paths: &[PathBuf]) -> Result<String> {
+    if !is_sass(path) {
+        return path.read();
+    }
+
+    tracing::debug!("compiling {path:?} ...");
+    let options = grass::Options::default().load_paths(load_paths);
+    grass::from_path(path, &options)
+        .map_err(|e| anyhow!("Failed to compile {}: {e}", path.display()))
+}
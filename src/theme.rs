@@ -0,0 +1,104 @@
+//! Theme packages.
+//!
+//! A theme package is a directory with `theme.toml`, selected with
+//! `theme = "themes/foo"` in `sonata.toml`:
+//!
+//! ```text
+//! themes/foo
+//! ├── theme.toml     # name, extends and the defaults of sonata.toml
+//! ├── templates      # overridden by the templates of the site
+//! ├── static         # copied to the output directory
+//! ├── theme.css
+//! ├── highlight.css
+//! └── highlight.js
+//! ```
+//!
+//! ```toml
+//! # themes/foo/theme.toml
+//! name = "foo"
+//! extends = "base"   # themes/base, relative to the parent of the theme.
+//!
+//! [defaults]
+//! description = "A site built with foo."
+//! ```
+
+use crate::utils::{self, Read};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The manifest of a theme package.
+pub const THEME_MANIFEST: &str = "theme.toml";
+
+/// A theme package.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Theme {
+    /// The name of the theme.
+    #[serde(default)]
+    pub name: String,
+    /// The theme to extend, relative to the parent of this theme.
+    #[serde(default)]
+    pub extends: Option<PathBuf>,
+    /// The default values of `sonata.toml`.
+    #[serde(default)]
+    pub defaults: toml::Table,
+    /// The directory of the theme.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Theme {
+    /// If the path is a theme package.
+    pub fn is_package(path: &Path) -> bool {
+        path.join(THEME_MANIFEST).is_file()
+    }
+
+    /// Load the theme package from the directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let toml = path.join(THEME_MANIFEST);
+        let mut theme: Self =
+            toml::from_str(&toml.read()?).map_err(|e| anyhow!("Failed to parse {toml:?}: {e}"))?;
+
+        theme.path = path.to_path_buf();
+        Ok(theme)
+    }
+
+    /// Load the theme and the themes it extends, the theme itself comes
+    /// first, returns nothing if the path is not a theme package.
+    pub fn chain(path: &Path) -> Result<Vec<Self>> {
+        let mut themes: Vec<Self> = Vec::new();
+        let mut next = Self::is_package(path).then(|| path.to_path_buf());
+        while let Some(path) = next {
+            let canonical = utils::canonicalize(&path)?;
+            if themes
+                .iter()
+                .any(|t| utils::canonicalize(&t.path).ok().as_ref() == Some(&canonical))
+            {
+                return Err(anyhow!("Theme {path:?} extends itself"));
+            }
+
+            let theme = Self::load(&path)?;
+            next = match &theme.extends {
+                Some(extends) => {
+                    let parent = path.parent().unwrap_or(Path::new("."));
+                    Some(parent.join(extends))
+                }
+                None => None,
+            };
+
+            themes.push(theme);
+        }
+
+        Ok(themes)
+    }
+
+    /// The templates of the theme.
+    pub fn templates(&self) -> PathBuf {
+        self.path.join("templates")
+    }
+
+    /// The static assets of the theme.
+    pub fn assets(&self) -> PathBuf {
+        self.path.join("static")
+    }
+}
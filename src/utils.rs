@@ -1,8 +1,9 @@
 //! sonata utils.
 
+use crate::images::Images;
 use anyhow::{anyhow, Result};
 use colored::Colorize;
-use pulldown_cmark::{html, Options, Parser};
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -52,13 +53,9 @@ pub trait Prefix: AsRef<Path> + Sized {
 impl Prefix for PathBuf {
     fn is_sub(&self, path: impl AsRef<Path>) -> Result<bool> {
         let ancestor = fs::canonicalize(self)?;
-        let sub = fs::canonicalize(path)?;
+        let sub = canonicalize(path)?;
 
-        Ok(sub
-            .as_os_str()
-            .to_string_lossy()
-            .to_string()
-            .contains(ancestor.as_os_str().to_string_lossy().to_string().as_str()))
+        Ok(sub.starts_with(ancestor))
     }
 
     fn prefix(&mut self, prefix: impl AsRef<Path>) {
@@ -68,6 +65,57 @@ impl Prefix for PathBuf {
     }
 }
 
+/// Canonicalize a path which may not exist anymore.
+///
+/// Resolves the closest existing ancestor and appends the rest of
+/// the path, this is required for checking removed or renamed files.
+pub fn canonicalize(path: impl AsRef<Path>) -> Result<PathBuf> {
+    let path = path.as_ref();
+    if path.exists() {
+        return Ok(fs::canonicalize(path)?);
+    }
+
+    let mut rest = Vec::new();
+    let mut ancestor = path;
+    while !ancestor.exists() {
+        rest.push(
+            ancestor
+                .file_name()
+                .ok_or_else(|| anyhow!("Failed to canonicalize path: {}", path.display()))?,
+        );
+        ancestor = ancestor
+            .parent()
+            .ok_or_else(|| anyhow!("Failed to canonicalize path: {}", path.display()))?;
+    }
+
+    let mut canonical = fs::canonicalize(ancestor)?;
+    canonical.extend(rest.into_iter().rev());
+    Ok(canonical)
+}
+
+/// The files in the directory recursively, relative to the directory.
+pub fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(root, &path, files)?;
+            } else {
+                files.push(path.strip_prefix(root)?.to_path_buf());
+            }
+        }
+
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    if dir.is_dir() {
+        walk(dir, dir, &mut files)?;
+    }
+
+    Ok(files)
+}
+
 /// Parse markdown to html.
 pub fn markdown(content: &str) -> String {
     let mut html = String::new();
@@ -75,6 +123,67 @@ pub fn markdown(content: &str) -> String {
     html
 }
 
+/// Parse markdown to html, the images are rendered as responsive
+/// `<picture>`s by the image pipeline.
+pub fn markdown_with(content: &str, images: &Images) -> Result<String> {
+    let mut events = Vec::new();
+    let mut parser = Parser::new_ext(content, Options::all());
+    while let Some(event) = parser.next() {
+        let Event::Start(Tag::Image(kind, src, title)) = event else {
+            events.push(event);
+            continue;
+        };
+
+        // the alt text of the image, until the end of the image.
+        let mut alt = String::new();
+        let mut nested = Vec::new();
+        for event in parser.by_ref() {
+            match &event {
+                Event::End(Tag::Image(..)) => break,
+                Event::Text(text) | Event::Code(text) => alt.push_str(text),
+                _ => {}
+            }
+            nested.push(event);
+        }
+
+        match images.picture(&src, &alt, &title)? {
+            Some(picture) => events.push(Event::Html(picture.into())),
+            None => {
+                events.push(Event::Start(Tag::Image(kind, src.clone(), title.clone())));
+                events.extend(nested);
+                events.push(Event::End(Tag::Image(kind, src, title)));
+            }
+        }
+    }
+
+    let mut html = String::new();
+    html::push_html(&mut html, events.into_iter());
+    Ok(html)
+}
+
+/// Join the path to the base URL, URLs with a scheme, protocol-relative
+/// URLs and fragments are returned as they are.
+pub fn url(base: &str, path: &str) -> String {
+    let external = path.starts_with("//")
+        || path.starts_with('#')
+        || path.split_once(':').is_some_and(|(scheme, _)| {
+            !scheme.is_empty()
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        });
+
+    if external {
+        return path.into();
+    }
+
+    format!(
+        "{}/{}",
+        base.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    )
+}
+
 /// Find the directory includes `sonata.toml`.
 pub fn find_proj(base: &Path) -> Result<PathBuf> {
     if base.join("sonata.toml").exists() {
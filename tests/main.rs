@@ -1,17 +1,36 @@
 //! Main tests for sonata.
 
 use anyhow::Result;
-use sonata::{App, Manifest, Post};
-use std::path::PathBuf;
+use sonata::{
+    App, Manifest, Post, ASSET_MANIFEST, BUNDLE_INDEX, MINIMAL_MANIFEST, OG_DIR, TEMPLATE_POST,
+};
+use std::{fs, path::PathBuf};
 
 fn manifest() -> Result<Manifest> {
     Manifest::load(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("blog"))
 }
 
+/// A fresh site in the temp directory with the minimal manifest and
+/// the extra toml, unique to the test and the process.
+fn site(name: &str, extra: &str) -> Result<PathBuf> {
+    let root = std::env::temp_dir().join(format!("sonata-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("posts"))?;
+    fs::write(
+        root.join("sonata.toml"),
+        format!("{MINIMAL_MANIFEST}{extra}"),
+    )?;
+    Ok(root)
+}
+
 #[test]
 fn render() -> Result<()> {
     let mut app: App<'_> = manifest()?.try_into()?;
     app.render()?;
+    assert!(app.manifest.out.join("404.html").exists());
+
+    // the app is cloneable with the template engine.
+    app.clone().render_404()?;
     Ok(())
 }
 
@@ -26,3 +45,357 @@ fn posts() -> Result<()> {
     manifest()?.posts()?;
     Ok(())
 }
+
+#[test]
+fn remove_post() -> Result<()> {
+    let root = site("remove-post", "")?;
+
+    let post = root.join("posts/2024-01-01-removed.md");
+    fs::write(&post, TEMPLATE_POST.trim())?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let html = root.join("out/posts/2024-01-01-removed.html");
+    assert!(html.exists());
+
+    fs::remove_file(&post)?;
+    app.crender(vec![post])?;
+    assert!(!html.exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn render_jinja() -> Result<()> {
+    let root = site("render-jinja", "engine = \"jinja\"\nbase = \"/sub/\"\n")?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let index = fs::read_to_string(root.join("out/index.html"))?;
+    assert!(index.contains(r#"href="/sub/posts/2024-01-01-hello.html""#));
+    assert!(root.join("out/posts/2024-01-01-hello.html").exists());
+    assert!(root.join("out/404.html").exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn theme_package() -> Result<()> {
+    let root = site("theme-package", "theme = \"themes/foo\"\n")?;
+    for dir in ["templates", "themes/base/templates", "themes/foo/templates"] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+
+    fs::write(
+        root.join("themes/base/theme.toml"),
+        "name = \"base\"\n[defaults]\ndescription = \"from base\"\n",
+    )?;
+    fs::write(
+        root.join("themes/base/theme.css"),
+        "@import \"base.css\";\n/* base */",
+    )?;
+    fs::write(
+        root.join("themes/base/base.css"),
+        "body {\n  color: red;\n}",
+    )?;
+    fs::write(
+        root.join("themes/base/templates/nav.hbs"),
+        "<nav>base</nav>",
+    )?;
+    fs::write(
+        root.join("themes/base/templates/footer.hbs"),
+        "<footer>base</footer>",
+    )?;
+    fs::write(
+        root.join("themes/foo/theme.toml"),
+        "name = \"foo\"\nextends = \"base\"\n",
+    )?;
+    fs::write(
+        root.join("themes/foo/templates/footer.hbs"),
+        "<footer>foo</footer>",
+    )?;
+    fs::write(root.join("templates/nav.hbs"), "<nav>site</nav>")?;
+    fs::write(
+        root.join("themes/foo/post.css"),
+        ".from-css { color: red; }",
+    )?;
+    fs::write(
+        root.join("themes/foo/post.scss"),
+        ".from-scss { color: red; }",
+    )?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    assert_eq!(app.manifest.description, "from base");
+    app.render()?;
+
+    let index = fs::read_to_string(root.join("out/index.html"))?;
+    assert!(index.contains("<nav>site</nav>"));
+    assert!(index.contains("<footer>foo</footer>"));
+    assert!(!index.contains("post.css"));
+    assert_eq!(
+        fs::read_to_string(root.join("out/theme.css"))?,
+        "body{color:red}\n/*# sourceMappingURL=theme.css.map */\n"
+    );
+
+    // the page stylesheet is linked by the posts, css wins over scss.
+    let post = fs::read_to_string(root.join("out/posts/2024-01-01-hello.html"))?;
+    assert!(post.contains("<link rel=\"stylesheet\" href=\"/post.css\">"));
+    assert!(fs::read_to_string(root.join("out/post.css"))?.contains(".from-css"));
+
+    // `--theme` is resolved against the root with the theme defaults.
+    fs::write(root.join("sonata.toml"), MINIMAL_MANIFEST)?;
+    let manifest = Manifest::load_with(
+        &root,
+        Manifest {
+            theme: "themes/foo".into(),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(manifest.description, "from base");
+    assert_eq!(manifest.themes.len(), 2);
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn scss_theme() -> Result<()> {
+    let root = site("scss-theme", "")?;
+    fs::create_dir_all(root.join("theme"))?;
+    fs::write(root.join("theme/_vars.scss"), "$fg: #333;")?;
+    fs::write(
+        root.join("theme/theme.scss"),
+        "@use \"vars\";\nbody { p { color: vars.$fg; } }",
+    )?;
+    fs::write(root.join("theme/post.scss"), "article { margin: 0; }")?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let theme = fs::read_to_string(root.join("out/theme.css"))?;
+    assert!(theme.starts_with("body p{color:#333}"));
+    assert!(theme.ends_with("/*# sourceMappingURL=theme.css.map */\n"));
+    let map = fs::read_to_string(root.join("out/theme.css.map"))?;
+    assert!(map.contains("\"sources\":[\"theme.scss\"]"));
+    assert!(!map.contains("\"mappings\":\"\""));
+    assert!(root.join("out/post.css").exists());
+    assert!(!root.join("out/_vars.css").exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn fingerprint() -> Result<()> {
+    let root = site("fingerprint", "fingerprint = true\n")?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let theme = app.assets["theme.css"].clone();
+    assert!(theme.starts_with("theme.") && theme.ends_with(".css") && theme.len() == 18);
+    assert!(root.join("out").join(&theme).exists());
+    assert!(root.join("out/theme.css").exists());
+
+    let assets: sonata::Assets =
+        serde_json::from_str(&fs::read_to_string(root.join("out").join(ASSET_MANIFEST))?)?;
+    assert_eq!(assets, app.assets);
+
+    let index = fs::read_to_string(root.join("out/index.html"))?;
+    assert!(index.contains(&format!("href=\"/{theme}\"")));
+
+    // the hashed files of the previous builds are removed.
+    fs::create_dir_all(root.join("theme"))?;
+    fs::write(root.join("theme/theme.css"), "body { margin: 0; }")?;
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+    assert_ne!(app.assets["theme.css"], theme);
+    assert!(!root.join("out").join(&theme).exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn precompress() -> Result<()> {
+    use std::io::Read;
+
+    let root = site(
+        "precompress",
+        "precompress = true\ncompress_threshold = 256\n",
+    )?;
+    fs::create_dir_all(root.join("out"))?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+    fs::write(root.join("out/removed.html.gz"), "stale")?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let out = root.join("out");
+    let index = fs::read(out.join("index.html"))?;
+    let mut html = Vec::new();
+    flate2::read::GzDecoder::new(fs::File::open(out.join("index.html.gz"))?)
+        .read_to_end(&mut html)?;
+    assert_eq!(html, index);
+    assert!(fs::metadata(out.join("index.html.br"))?.len() < index.len() as u64);
+    assert!(!out.join("removed.html.gz").exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn page_bundle() -> Result<()> {
+    let root = site("page-bundle", "")?;
+    fs::create_dir_all(root.join("posts/2024-01-02-foo/img"))?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+    fs::write(
+        root.join("posts/2024-01-02-foo").join(BUNDLE_INDEX),
+        "![cover](img/cover.png)",
+    )?;
+    fs::write(root.join("posts/2024-01-02-foo/img/cover.png"), "png")?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let post = fs::read_to_string(root.join("out/posts/2024-01-02-foo/index.html"))?;
+    assert!(post.contains("<img src=\"img/cover.png\" alt=\"cover\""));
+    assert!(root.join("out/posts/2024-01-02-foo/img/cover.png").exists());
+    assert!(!root.join("out/posts/2024-01-02-foo/index.md").exists());
+
+    let index = fs::read_to_string(root.join("out/index.html"))?;
+    assert!(index.contains("posts/2024-01-02-foo/index.html"));
+    assert!(index.find("Foo") < index.find("Hello"));
+
+    fs::remove_dir_all(root.join("posts/2024-01-02-foo"))?;
+    app.crender(vec![root.join("posts/2024-01-02-foo/index.md")])?;
+    assert!(!root.join("out/posts/2024-01-02-foo").exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn og_image() -> Result<()> {
+    let root = site(
+        "og-image",
+        "og_image = true\nurl = \"https://example.com/\"\n",
+    )?;
+    fs::write(root.join("posts/2024-01-01-hello.md"), TEMPLATE_POST.trim())?;
+    fs::write(
+        root.join("posts/2024-01-02-cover.md"),
+        TEMPLATE_POST
+            .trim()
+            .replacen("---\n", "---\nimage: /public/cover.png\n", 1),
+    )?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+
+    let png = fs::read(root.join("out").join(OG_DIR).join("2024-01-01-hello.png"))?;
+    assert!(png.starts_with(b"\x89PNG"));
+    assert!(!root
+        .join("out")
+        .join(OG_DIR)
+        .join("2024-01-02-cover.png")
+        .exists());
+
+    let hello = fs::read_to_string(root.join("out/posts/2024-01-01-hello.html"))?;
+    assert!(hello.contains(
+        "<meta property=\"og:image\" content=\"https://example.com/og/2024-01-01-hello.png\">"
+    ));
+    let cover = fs::read_to_string(root.join("out/posts/2024-01-02-cover.html"))?;
+    assert!(cover
+        .contains("<meta property=\"og:image\" content=\"https://example.com/public/cover.png\">"));
+
+    fs::remove_file(root.join("posts/2024-01-01-hello.md"))?;
+    app.crender(vec![root.join("posts/2024-01-01-hello.md")])?;
+    assert!(!root
+        .join("out")
+        .join(OG_DIR)
+        .join("2024-01-01-hello.png")
+        .exists());
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn image_metadata() -> Result<()> {
+    let root = site(
+        "image-metadata",
+        "image_widths = [16]\nimage_formats = [\"webp\"]\n",
+    )?;
+    for dir in ["posts/2024-01-02-foo", "public/photos"] {
+        fs::create_dir_all(root.join(dir))?;
+    }
+
+    // a jpeg with the EXIF of the orientation and a GPS marker.
+    let mut jpeg = std::io::Cursor::new(Vec::new());
+    image::DynamicImage::new_rgb8(40, 20).write_to(&mut jpeg, image::ImageFormat::Jpeg)?;
+    let mut exif =
+        b"Exif\0\0MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x01\0\0\0\0\0\0".to_vec();
+    exif.extend_from_slice(b"GPSLatitude 48.8584");
+    let mut bytes = jpeg.into_inner();
+    let app1 = [
+        &[0xff, 0xe1][..],
+        &(exif.len() as u16 + 2).to_be_bytes(),
+        &exif,
+    ]
+    .concat();
+    bytes.splice(2..2, app1);
+
+    fs::write(root.join("public/photos/photo.jpg"), &bytes)?;
+    fs::write(root.join("posts/2024-01-02-foo/photo.jpg"), &bytes)?;
+    fs::write(
+        root.join("posts/2024-01-01-hello.md"),
+        format!(
+            "{}\n\n![photo](/public/photos/photo.jpg)",
+            TEMPLATE_POST.trim()
+        ),
+    )?;
+    fs::write(
+        root.join("posts/2024-01-02-foo").join(BUNDLE_INDEX),
+        "![photo](photo.jpg)",
+    )?;
+
+    let mut app: App<'_> = Manifest::load(&root)?.try_into()?;
+    app.render()?;
+    app.crender(vec![root.join("public/photos/photo.jpg")])?;
+
+    let out = root.join("out");
+    let copied = ["public/photos/photo.jpg", "posts/2024-01-02-foo/photo.jpg"];
+    for path in copied {
+        assert!(
+            fs::read(out.join(path))?.starts_with(&[0xff, 0xd8]),
+            "{path}"
+        );
+        assert_eq!(image::open(out.join(path))?.width(), 40, "{path}");
+    }
+
+    let mut dirs = vec![out];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+
+            let bytes = fs::read(&path)?;
+            let found = |marker: &[u8]| bytes.windows(marker.len()).any(|w| w == marker);
+            assert!(!found(b"Exif\0\0") && !found(b"GPSLatitude"), "{path:?}");
+        }
+    }
+
+    fs::remove_dir_all(&root)?;
+    Ok(())
+}